@@ -0,0 +1,90 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use getset::Getters;
+
+use crate::collapse::{solve, Backtracking, Contradiction, Params, Wave};
+
+/// Independent generations with the seeds `first_seed..first_seed + count`, wrapping
+/// around after `u64::MAX`, spread over `threads` worker threads.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, new, Getters)]
+#[getset(get = "pub")]
+pub struct Batch {
+    /// The seed of the first generation.
+    first_seed: u64,
+    /// The number of generations.
+    count: usize,
+    /// The number of worker threads, at least one is used.
+    threads: usize,
+}
+
+/// How a batch went: the number of solved waves, the seeds that ran into a
+/// contradiction and the seeds whose result could not be written, by seed.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Getters)]
+#[getset(get = "pub")]
+pub struct Report {
+    /// The number of waves solved and written.
+    solved: usize,
+    /// The seeds that could not be solved, with their last contradiction.
+    contradictions: Vec<(u64, Contradiction)>,
+    /// The seeds whose solved wave could not be written, with the error.
+    errors: Vec<(u64, String)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} solved, {} contradictions, {} failed to write",
+            self.solved,
+            self.contradictions.len(),
+            self.errors.len()
+        )
+    }
+}
+
+impl Batch {
+    /// Solves a copy of `wave` for every seed and hands each solved one to `write`.
+    pub fn run<W>(
+        &self,
+        params: &Params,
+        wave: &Wave,
+        backtracking: &Backtracking,
+        write: W,
+    ) -> Report
+    where
+        W: Fn(u64, &Wave) -> Result<(), String> + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let report = Mutex::new(Report::default());
+        thread::scope(|scope| {
+            for _ in 0..self.threads.max(1) {
+                scope.spawn(|| loop {
+                    let job = next.fetch_add(1, Ordering::Relaxed);
+                    if job >= self.count {
+                        break;
+                    }
+                    let seed = self.first_seed.wrapping_add(job as u64);
+                    let mut solved = wave.clone();
+                    let outcome = solve(params, &mut solved, seed, backtracking)
+                        .map(|()| write(seed, &solved));
+                    let mut report = report.lock().unwrap();
+                    match outcome {
+                        Ok(Ok(())) => report.solved += 1,
+                        Ok(Err(error)) => report.errors.push((seed, error)),
+                        Err(contradiction) => report.contradictions.push((seed, contradiction)),
+                    }
+                });
+            }
+        });
+        let mut report = report.into_inner().unwrap();
+        report.contradictions.sort_by_key(|(seed, _)| *seed);
+        report.errors.sort_by_key(|(seed, _)| *seed);
+        report
+    }
+}
+
+#[cfg(test)]
+mod batch_test;
@@ -0,0 +1,102 @@
+use std::path::Path;
+
+use super::*;
+use crate::fixtures::{field, free_borders};
+use crate::model::Model;
+use crate::parser;
+
+#[test]
+fn every_seed_runs_once() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+    let borders = free_borders();
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let wave = Wave::filled(&params, 8, 8).unwrap();
+    let written = Mutex::new(Vec::new());
+    let batch = Batch::new(10, 12, 4);
+
+    let report = batch.run(&params, &wave, &Backtracking::default(), |seed, wave| {
+        assert!(wave
+            .cells()
+            .elements_row_major_iter()
+            .all(|tiles| tiles.len() == 1));
+        written.lock().unwrap().push((seed, wave.clone()));
+        Ok(())
+    });
+    assert_eq!(*report.solved(), 12);
+    let mut written = written.into_inner().unwrap();
+    written.sort_by_key(|(seed, _)| *seed);
+    assert_eq!(
+        written.iter().map(|(seed, _)| *seed).collect::<Vec<_>>(),
+        (10..22).collect::<Vec<_>>()
+    );
+
+    // the same seed solves the same wave no matter which thread runs it
+    let mut single = wave.clone();
+    solve(&params, &mut single, 15, &Backtracking::default()).unwrap();
+    assert_eq!(written[5].1, single);
+}
+
+#[test]
+fn counts_contradictions_and_errors() {
+    // the right side fits no left side, so the tile can only be stacked vertically
+    let fields = vec![field("a", ["i-A", "i-X", "i-A", "i-Y"])];
+    let model = Model::new(&fields);
+    let borders = free_borders();
+    let params = Params::new(&fields, &model, &borders, false);
+    let column = Wave::filled(&params, 1, 2).unwrap();
+    let report = Batch::new(0, 6, 3).run(&params, &column, &Backtracking::default(), |seed, _| {
+        if seed % 2 == 0 {
+            Ok(())
+        } else {
+            Err("disk full".to_string())
+        }
+    });
+
+    assert_eq!(*report.solved(), 3);
+    assert_eq!(
+        report
+            .errors()
+            .iter()
+            .map(|(seed, _)| *seed)
+            .collect::<Vec<_>>(),
+        vec![1, 3, 5]
+    );
+    assert!(report.contradictions().is_empty());
+    assert_eq!(
+        report.to_string(),
+        "3 solved, 0 contradictions, 3 failed to write"
+    );
+
+    let row = Wave::filled(&params, 2, 1).unwrap();
+    let report = Batch::new(7, 4, 2).run(&params, &row, &Backtracking::default(), |_, _| Ok(()));
+    assert_eq!(*report.solved(), 0);
+    assert_eq!(
+        report
+            .contradictions()
+            .iter()
+            .map(|(seed, _)| *seed)
+            .collect::<Vec<_>>(),
+        vec![7, 8, 9, 10]
+    );
+}
+
+#[test]
+fn seeds_wrap_around() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+    let borders = free_borders();
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let wave = Wave::filled(&params, 4, 4).unwrap();
+    let seeds = Mutex::new(Vec::new());
+
+    let report =
+        Batch::new(u64::MAX, 2, 1).run(&params, &wave, &Backtracking::default(), |seed, _| {
+            seeds.lock().unwrap().push(seed);
+            Ok(())
+        });
+    assert_eq!(*report.solved(), 2);
+    let mut seeds = seeds.into_inner().unwrap();
+    seeds.sort();
+    assert_eq!(seeds, vec![0, u64::MAX]);
+}
@@ -0,0 +1,132 @@
+use std::str::FromStr;
+
+use crate::collapse::fits;
+use crate::model::Model;
+use crate::tileset::TileSet;
+
+/// What lies beyond a single position on the edge of the grid.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Boundary {
+    /// Any tile may sit on the edge.
+    Free,
+    /// The side facing the edge has to fit this label, e.g. `i-Substrate`.
+    Side(String),
+    /// The tile with this index lies beyond the edge.
+    Tile(usize),
+}
+
+/// The boundaries along one edge of the grid, either the same everywhere
+/// or one for every position from left to right or top to bottom.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Border {
+    /// The same boundary along the whole edge.
+    All(Boundary),
+    /// One boundary for every position along the edge.
+    PerPosition(Vec<Boundary>),
+}
+
+impl Border {
+    /// An edge any tile may lie on.
+    pub fn free() -> Border {
+        Border::All(Boundary::Free)
+    }
+
+    /// One boundary for each of the `len` positions along the edge. A model learned
+    /// from an example map has no side labels, so it only takes free and tile borders.
+    pub fn positions(&self, model: &Model, len: usize) -> Result<Vec<Boundary>, String> {
+        let positions = match self {
+            Border::All(boundary) => vec![boundary.clone(); len],
+            Border::PerPosition(boundaries) if boundaries.len() == len => boundaries.clone(),
+            Border::PerPosition(boundaries) => {
+                return Err(format!(
+                    "border has {} positions but the edge is {} fields long",
+                    boundaries.len(),
+                    len
+                ))
+            }
+        };
+        if model.adjacency().is_some() {
+            if let Some(label) = positions.iter().find_map(|boundary| match boundary {
+                Boundary::Side(label) => Some(label),
+                _ => None,
+            }) {
+                return Err(format!(
+                    "border side {} cannot be used with a set learned from an example map",
+                    label
+                ));
+            }
+        }
+        match positions.iter().find_map(|boundary| match boundary {
+            Boundary::Tile(tile) if *tile >= model.tile_count() => Some(tile),
+            _ => None,
+        }) {
+            Some(tile) => Err(format!(
+                "border tile {} is not in the set of {} tiles",
+                tile,
+                model.tile_count()
+            )),
+            None => Ok(positions),
+        }
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = String;
+
+    /// `free`, a tile index or a side label.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "free" => Ok(Boundary::Free),
+            _ if s.contains('-') => Ok(Boundary::Side(s.to_string())),
+            _ => s
+                .parse()
+                .map(Boundary::Tile)
+                .map_err(|_| format!("{} is neither free, a tile nor a side label", s)),
+        }
+    }
+}
+
+impl FromStr for Border {
+    type Err = String;
+
+    /// A single boundary for the whole edge or a comma separated one per position.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(',') {
+            s.split(',')
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map(Border::PerPosition)
+        } else {
+            s.parse().map(Border::All)
+        }
+    }
+}
+
+impl Boundary {
+    /// The tiles that may sit next to the boundary when it lies in direction `dir`.
+    pub fn allowed(&self, model: &Model, dir: usize) -> TileSet {
+        match self {
+            Boundary::Free => TileSet::full(model.tile_count()),
+            Boundary::Side(label) => {
+                let mut allowed = TileSet::empty(model.tile_count());
+                (0..model.tile_count())
+                    .filter(|&tile| fits(model.label(tile, dir), label))
+                    .for_each(|tile| allowed.insert(tile));
+                allowed
+            }
+            Boundary::Tile(tile) => model.compatible()[model.opposite(dir)][*tile].clone(),
+        }
+    }
+
+    /// The side labels the boundary shows towards the grid, none if it is free.
+    pub fn labels(&self, model: &Model, dir: usize) -> Vec<String> {
+        match self {
+            Boundary::Free => Vec::new(),
+            Boundary::Side(label) => vec![label.clone()],
+            Boundary::Tile(tile) => vec![model.label(*tile, model.opposite(dir)).to_string()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod boundary_test;
@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use super::*;
+use crate::collapse::{solve, Backtracking, Params, Wave};
+use crate::parser;
+
+#[test]
+fn parse_borders() {
+    assert_eq!("free".parse(), Ok(Border::All(Boundary::Free)));
+    assert_eq!("3".parse(), Ok(Border::All(Boundary::Tile(3))));
+    assert_eq!(
+        "i-Substrate,free".parse(),
+        Ok(Border::PerPosition(vec![
+            Boundary::Side("i-Substrate".to_string()),
+            Boundary::Free
+        ]))
+    );
+    assert!("substrate".parse::<Border>().is_err());
+}
+
+#[test]
+fn positions_have_to_match_the_edge() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+    let border = Border::PerPosition(vec![Boundary::Free, Boundary::Tile(0)]);
+
+    assert_eq!(border.positions(&model, 2).unwrap().len(), 2);
+    assert!(border.positions(&model, 3).is_err());
+    assert!(Border::All(Boundary::Tile(1000))
+        .positions(&model, 2)
+        .is_err());
+
+    let borders = [border.clone(), border.clone(), border.clone(), border];
+    let params = Params::new(set.fields(), &model, &borders, false);
+    assert!(Wave::filled(&params, 2, 2).is_ok());
+    assert!(Wave::filled(&params, 3, 2).is_err());
+}
+
+#[test]
+fn learned_sets_take_no_side_borders() {
+    let set = parser::load(Path::new("res/circuit_example.json")).unwrap();
+    let model = set.model();
+    let substrate = Border::All(Boundary::Side("i-Substrate".to_string()));
+
+    assert!(substrate
+        .positions(&model, 2)
+        .unwrap_err()
+        .contains("learned from an example map"));
+    assert!(Border::All(Boundary::Tile(0)).positions(&model, 2).is_ok());
+}
+
+#[test]
+fn substrate_frame() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+    let substrate = Border::All(Boundary::Side("i-Substrate".to_string()));
+    let borders = [(); 4].map(|_| substrate.clone());
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let mut wave = Wave::filled(&params, 8, 8).unwrap();
+
+    assert!(solve(&params, &mut wave, 3, &Backtracking::default()).is_ok());
+    let side = |y, x, dir| {
+        let tile = wave.cells().get(y, x).unwrap().iter().next().unwrap();
+        model.label(tile, dir).to_string()
+    };
+    for i in 0..8 {
+        assert_eq!(side(0, i, 0), "i-Substrate");
+        assert_eq!(side(i, 7, 1), "i-Substrate");
+        assert_eq!(side(7, i, 2), "i-Substrate");
+        assert_eq!(side(i, 0, 3), "i-Substrate");
+    }
+}
+
+#[test]
+fn tile_boundary_allows_fitting_tiles() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+
+    let allowed = Boundary::Tile(0).allowed(&model, 1);
+    for tile in 0..model.tile_count() {
+        assert_eq!(
+            allowed.contains(tile),
+            fits(model.label(tile, 1), model.label(0, 3))
+        );
+    }
+    assert_eq!(Boundary::Free.allowed(&model, 0).len(), model.tile_count());
+}
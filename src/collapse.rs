@@ -1,143 +1,880 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
 use array2d::Array2D;
 use getset::Getters;
 use itertools::Itertools;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::boundary::{Border, Boundary};
+use crate::connection::Connection;
+use crate::generator::Generator;
+use crate::heuristic::{Entropy, Heuristic};
+use crate::limit::Limit;
+use crate::model::Model;
+use crate::tileset::{Cells, TileSet, Tiles};
+use crate::topology::Topology;
+use crate::weights::Weights;
 
+/// One tile of a set in one orientation: the image drawn for it, its clockwise
+/// rotation in degrees, its sides in the order of the topology's directions and how
+/// often it is chosen relative to the others.
 #[derive(Clone, PartialEq, Eq, Hash, Debug, new, Getters)]
 #[getset(get = "pub")]
 pub struct Field {
+    /// The image file, relative to the directory of the set.
     img_name: String,
+    /// The clockwise rotation in degrees.
     rotation: i32,
-    sides: [String; 4],
+    /// The side labels in the order of the directions of the topology.
+    sides: Vec<String>,
+    /// How often the field is chosen relative to the others.
     weight: u32,
+    /// Whether the image is mirrored left to right before it is rotated.
+    #[new(default)]
+    mirrored: bool,
+}
+
+impl Field {
+    /// Marks the field as drawn mirrored.
+    pub fn with_mirrored(self, mirrored: bool) -> Field {
+        Field { mirrored, ..self }
+    }
 }
 
+/// Everything a wave is collapsed with: the fields, the model of which fit together,
+/// the edges of the grid and the optional weights, limits, connections and heuristic.
 #[derive(new, Getters)]
 #[getset(get = "pub")]
 pub struct Params<'p> {
+    /// Every field of the set, indexed by tile.
     fields: &'p Vec<Field>,
-    sides: &'p [Vec<Vec<&'p Field>>; 4],
+    /// Which tiles fit next to each other.
+    model: &'p Model,
+    /// What lies beyond the top, right, bottom and left edges.
+    borders: &'p [Border; 4],
+    /// Wraps the grid around at its edges instead of using `borders`, so it tiles seamlessly.
+    periodic: bool,
+    /// Biases the weights of the fields by position, see [`Params::with_weights`].
+    #[new(default)]
+    weights: Option<&'p Weights>,
+    /// Bounds how often tiles may occur, enforced by the [`Generator`] after every step.
+    #[new(default)]
+    limits: &'p [Limit],
+    /// Networks that have to stay connected, enforced like the limits.
+    #[new(default)]
+    connections: &'p [Connection],
+    /// Chooses the cell to observe next, the lowest entropy unless set otherwise.
+    #[new(value = "&Entropy")]
+    heuristic: &'p dyn Heuristic,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, new)]
+impl<'p> Params<'p> {
+    /// Multiplies the weight of every field by its multiplier at the observed cell.
+    pub fn with_weights(self, weights: Option<&'p Weights>) -> Params<'p> {
+        Params { weights, ..self }
+    }
+
+    /// Bounds how often tiles may occur.
+    pub fn with_limits(self, limits: &'p [Limit]) -> Params<'p> {
+        Params { limits, ..self }
+    }
+
+    /// Requires networks of side labels to stay connected.
+    pub fn with_connections(self, connections: &'p [Connection]) -> Params<'p> {
+        Params {
+            connections,
+            ..self
+        }
+    }
+
+    /// Chooses the cells to observe with `heuristic` instead of the lowest entropy.
+    pub fn with_heuristic(self, heuristic: &'p dyn Heuristic) -> Params<'p> {
+        Params { heuristic, ..self }
+    }
+
+    /// The weight of `tile` at `pos` of `cells`. A learned model also scales it by how
+    /// often the tile was seen next to each decided neighbor.
+    pub fn weight(&self, tile: usize, pos: Coord, cells: &Cells) -> f64 {
+        let mut weight = self.fields[tile].weight as f64;
+        if let Some(weights) = self.weights {
+            weight *= weights.multiplier(tile, pos, cells.row_len(), cells.column_len());
+        }
+        if let Some(adjacency) = self.model.adjacency() {
+            // learned models are flat
+            for dir in 0..self.model.topology().sides() {
+                let decided = neighbor(self, cells, 1, pos, dir)
+                    .and_then(|other| cells.get(other.y, other.x)?.iter().exactly_one().ok());
+                if let Some(other) = decided {
+                    weight *= adjacency.frequency(tile, dir, other);
+                }
+            }
+        }
+        weight
+    }
+}
+
+/// Limits for undoing decisions after a contradiction: `depth` is how many past
+/// observations are remembered, `attempts` how often the solver may step back in total.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, new, Getters)]
+#[getset(get = "pub")]
+pub struct Backtracking {
+    /// How many past observations can be taken back.
+    depth: usize,
+    /// How often the solver may step back in total.
+    attempts: usize,
+}
+
+impl Default for Backtracking {
+    fn default() -> Self {
+        Backtracking::new(32, 256)
+    }
+}
+
+/// A cell of a wave, `x` counting columns and `y` rows from the top left.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, new, Getters)]
+#[getset(get = "pub")]
 pub struct Coord {
+    /// The column.
     x: usize,
+    /// The row, counting the rows of all layers of a volume.
     y: usize,
 }
 
-trait Dedup<T: PartialEq + Clone> {
-    fn clear_duplicates(&mut self);
+/// The wave cannot be collapsed anymore after a change at `pos`. If a cell ran out of
+/// candidates during propagation, holds the neighbor sides the cell was checked against
+/// and the fields removed from it in that last step, otherwise the `reason` tells what
+/// failed instead.
+#[derive(Clone, PartialEq, Eq, Debug, new, Getters)]
+#[getset(get = "pub")]
+pub struct Contradiction {
+    /// The cell that ran out of candidates, or the change after which the wave failed.
+    pos: Coord,
+    /// Per direction, the labels the neighbor could still offer the cell.
+    sides: Vec<Vec<String>>,
+    /// The last fields removed from the cell.
+    removed: Vec<Field>,
+    /// What failed, a cell running out of candidates unless set otherwise.
+    #[new(value = "Reason::Exhausted")]
+    reason: Reason,
 }
 
-impl<T: PartialEq + Clone> Dedup<T> for Vec<T> {
-    fn clear_duplicates(&mut self) {
-        let mut already_seen = Vec::new();
-        self.retain(|item| match already_seen.contains(item) {
-            true => false,
-            _ => {
-                already_seen.push(item.clone());
-                true
-            }
-        })
+/// Why a wave contradicts.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Reason {
+    /// The cell at the position ran out of candidates.
+    Exhausted,
+    /// `count` cells are already one of the tiles of a limit, more than its `max`.
+    TooMany {
+        /// The images of the tiles of the limit.
+        fields: Vec<String>,
+        /// The cells decided to be one of them.
+        count: usize,
+        /// The maximum of the limit.
+        max: usize,
+    },
+    /// Only `count` cells can still become one of the tiles of a limit, fewer than its
+    /// `min`.
+    TooFew {
+        /// The images of the tiles of the limit.
+        fields: Vec<String>,
+        /// The cells that can still become one of them.
+        count: usize,
+        /// The minimum of the limit.
+        min: usize,
+    },
+    /// The cells certain to belong to the network of the connection `label` can no
+    /// longer reach each other.
+    Disconnected {
+        /// The side label of the connection.
+        label: String,
+    },
+}
+
+impl Contradiction {
+    /// Fails for another reason than a cell running out of candidates.
+    pub fn with_reason(self, reason: Reason) -> Self {
+        Contradiction { reason, ..self }
     }
 }
 
-pub fn update_field(params: &Params, wave: &mut Array2D<Vec<&Field>>, pos: Coord) -> usize {
-    let mut next = find_real_neighbors(wave, pos);
-    let mut updates: usize = 0;
-    while let Some(pos) = next.pop() {
-        let neighbors = find_neighbors(params, wave, pos.clone());
-        if apply_constraints(wave.get_mut(pos.y, pos.x).unwrap(), &neighbors) {
-            next.append(&mut find_real_neighbors(wave, pos));
-            updates += 1;
-            next.clear_duplicates();
+impl fmt::Display for Contradiction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            Reason::Exhausted => write!(
+                f,
+                "field {}, {} ran out of candidates after removing [{}] for the sides [{}]",
+                self.pos.x,
+                self.pos.y,
+                self.removed.iter().map(|field| field.img_name()).join(", "),
+                self.sides.iter().map(|side| side.join("|")).join(", ")
+            ),
+            Reason::TooMany { fields, count, max } => write!(
+                f,
+                "after field {}, {} there are {} of [{}], but at most {} are allowed",
+                self.pos.x,
+                self.pos.y,
+                count,
+                fields.join(", "),
+                max
+            ),
+            Reason::TooFew { fields, count, min } => write!(
+                f,
+                "after field {}, {} only {} fields can become one of [{}], but at least {} \
+                 are needed",
+                self.pos.x,
+                self.pos.y,
+                count,
+                fields.join(", "),
+                min
+            ),
+            Reason::Disconnected { label } => write!(
+                f,
+                "after field {}, {} the {} network is split",
+                self.pos.x, self.pos.y, label
+            ),
         }
     }
-    updates
 }
 
-fn apply_constraints(target: &mut Vec<&Field>, sides: &[Vec<&str>; 4]) -> bool {
-    let old_len = target.len();
-    target.retain(|field: &&Field| {
-        field
-            .sides()
-            .iter()
-            .zip(sides)
-            .all(|(target_side, cmp_sides): (&String, &Vec<&str>)| {
-                cmp_sides
-                    .iter()
-                    .any(|cmp_side: &&str| fits(target_side, cmp_side))
-            })
-    });
-    // TODO save change
-    target.len() != old_len
+impl Error for Contradiction {}
+
+/// The candidates of every cell plus, for every cell, tile and direction, how many
+/// candidates of the neighbor in that direction still fit next to the tile, and the
+/// sums of the weights of each cell's candidates.
+/// A volume stores its `layers` one below the other, each with the same number of rows.
+/// Every removal is recorded until the diff is taken, so changes can be undone.
+#[derive(Clone, Debug, Getters)]
+pub struct Wave {
+    /// The candidates of every cell.
+    #[getset(get = "pub")]
+    cells: Cells,
+    /// The number of layers, 1 for a flat grid.
+    #[getset(get = "pub")]
+    layers: usize,
+    tiles: usize,
+    sides: usize,
+    edges: [Vec<Boundary>; 4],
+    support: Vec<u32>,
+    sums: Vec<Sums>,
+    worklist: Worklist,
+    diff: Diff,
+}
+
+// the sums only approximate the weights of the candidates, so they are left out
+impl PartialEq for Wave {
+    fn eq(&self, other: &Self) -> bool {
+        self.cells == other.cells
+            && self.layers == other.layers
+            && self.tiles == other.tiles
+            && self.sides == other.sides
+            && self.edges == other.edges
+            && self.support == other.support
+            && self.worklist == other.worklist
+            && self.diff == other.diff
+    }
+}
+
+impl Eq for Wave {}
+
+/// The number of candidates of a cell, the sum of their weights and the sum of each
+/// weight times its logarithm, kept up to date so the entropy needs no rescan.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+struct Sums {
+    count: usize,
+    weight: f64,
+    log_weight: f64,
+}
+
+impl Sums {
+    fn of(params: &Params, cells: &Cells, pos: Coord) -> Sums {
+        let mut sums = Sums::default();
+        for tile in cells.get(pos.y, pos.x).unwrap().iter() {
+            sums.add(params.weight(tile, pos, cells));
+        }
+        sums
+    }
+
+    fn add(&mut self, weight: f64) {
+        self.count += 1;
+        self.weight += weight;
+        self.log_weight += log_weight(weight);
+    }
+
+    fn subtract(&mut self, weight: f64) {
+        self.count -= 1;
+        self.weight -= weight;
+        self.log_weight -= log_weight(weight);
+    }
+
+    /// The Shannon entropy of the weights.
+    fn entropy(&self) -> f64 {
+        self.weight.ln() - self.log_weight / self.weight
+    }
+}
+
+fn log_weight(weight: f64) -> f64 {
+    if weight > 0.0 {
+        weight * weight.ln()
+    } else {
+        0.0
+    }
+}
+
+/// The candidates removed from cells in the order it happened, enough to take changes
+/// to a wave back and redo them exactly.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Diff {
+    removed: Vec<(Coord, TileSet)>,
+}
+
+impl Diff {
+    /// The cells that lost candidates, each once, in the order they first changed.
+    pub fn cells(&self) -> Vec<Coord> {
+        self.removed.iter().map(|(pos, _)| *pos).unique().collect()
+    }
+}
+
+/// Cells whose removed candidates still have to be propagated. Each cell is queued at
+/// most once and collects everything removed from it until it is visited.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Worklist {
+    queue: VecDeque<Coord>,
+    queued: Vec<bool>,
+    removed: Cells,
+}
+
+impl Worklist {
+    fn new(row_len: usize, column_len: usize, tiles: usize) -> Worklist {
+        Worklist {
+            queue: VecDeque::new(),
+            queued: vec![false; row_len * column_len],
+            removed: Cells::filled_with(&TileSet::empty(tiles), column_len, row_len),
+        }
+    }
+
+    fn push(&mut self, cell: usize, pos: Coord, removed: &TileSet) {
+        self.removed.insert(pos.y, pos.x, removed);
+        if !self.queued[cell] {
+            self.queued[cell] = true;
+            self.queue.push_back(pos);
+        }
+    }
+
+    fn pop(&mut self, row_len: usize) -> Option<(Coord, TileSet)> {
+        let pos = self.queue.pop_front()?;
+        let cell = pos.y * row_len + pos.x;
+        self.queued[cell] = false;
+        Some((pos, self.removed.take(pos.y, pos.x)))
+    }
+
+    fn clear(&mut self, row_len: usize) {
+        while self.pop(row_len).is_some() {}
+    }
+}
+
+impl Wave {
+    /// Fails if a border does not match the length of its edge of `cells`, if a
+    /// periodic hex grid has an odd number of rows and so cannot wrap around, or if a
+    /// connection point lies outside the grid.
+    pub fn new(params: &Params, cells: Cells) -> Result<Wave, String> {
+        Wave::layered(params, cells, 1)
+    }
+
+    /// A wave over `layers` layers stacked in the rows of `cells`.
+    pub fn layered(params: &Params, cells: Cells, layers: usize) -> Result<Wave, String> {
+        let tiles = params.model.tile_count();
+        let sides = params.model.topology().sides();
+        if layers == 0 || !cells.column_len().is_multiple_of(layers) {
+            return Err(format!(
+                "{} rows cannot be split into {} layers",
+                cells.column_len(),
+                layers
+            ));
+        }
+        let rows = cells.column_len() / layers;
+        if params.periodic && *params.model.topology() == Topology::Hex && rows % 2 == 1 {
+            return Err(format!(
+                "a periodic hex grid needs an even number of rows, not {}",
+                rows
+            ));
+        }
+        if let Some((connection, point)) = params.connections.iter().find_map(|connection| {
+            connection
+                .points()
+                .iter()
+                .find(|point| cells.get(point.y, point.x).is_none())
+                .map(|point| (connection, point))
+        }) {
+            return Err(format!(
+                "the {} connection point {}, {} is outside the {}x{} grid",
+                connection.label(),
+                point.x,
+                point.y,
+                cells.row_len(),
+                cells.column_len()
+            ));
+        }
+        let edges = if params.periodic {
+            Default::default()
+        } else {
+            let lens = [cells.row_len(), cells.column_len()];
+            let mut dirs = 0..4;
+            let edges = params
+                .borders
+                .clone()
+                .map(|border| border.positions(params.model, lens[dirs.next().unwrap() % 2]));
+            let [top, right, bottom, left] = edges;
+            [top?, right?, bottom?, left?]
+        };
+        let mut support = Vec::with_capacity(cells.num_elements() * tiles * sides);
+        for (y, x) in cells.indices_row_major() {
+            let pos = Coord::new(x, y);
+            let counts = (0..sides)
+                .map(|dir| match neighbor(params, &cells, layers, pos, dir) {
+                    Some(other) => {
+                        let candidates = cells.get(other.y, other.x).unwrap();
+                        (0..tiles)
+                            .map(|tile| {
+                                params.model.compatible()[dir][tile].common_len(candidates) as u32
+                            })
+                            .collect_vec()
+                    }
+                    None => {
+                        let allowed = edge(params, &cells, layers, &edges, pos, dir)
+                            .allowed(params.model, dir);
+                        (0..tiles)
+                            .map(|tile| allowed.contains(tile) as u32)
+                            .collect_vec()
+                    }
+                })
+                .collect_vec();
+            support.extend((0..tiles).flat_map(|tile| counts.iter().map(move |dir| dir[tile])));
+        }
+        let sums = cells
+            .indices_row_major()
+            .map(|(y, x)| Sums::of(params, &cells, Coord::new(x, y)))
+            .collect();
+        let worklist = Worklist::new(cells.row_len(), cells.column_len(), tiles);
+        Ok(Wave {
+            cells,
+            layers,
+            tiles,
+            sides,
+            edges,
+            support,
+            sums,
+            worklist,
+            diff: Diff::default(),
+        })
+    }
+
+    /// A wave where every cell may still become every tile.
+    pub fn filled(params: &Params, x_size: usize, y_size: usize) -> Result<Wave, String> {
+        let full = TileSet::full(params.model.tile_count());
+        Wave::new(params, Cells::filled_with(&full, y_size, x_size))
+    }
+
+    /// A filled volume of `z_size` layers, each `x_size` by `y_size` cells.
+    pub fn volume(
+        params: &Params,
+        x_size: usize,
+        y_size: usize,
+        z_size: usize,
+    ) -> Result<Wave, String> {
+        let full = TileSet::full(params.model.tile_count());
+        Wave::layered(
+            params,
+            Cells::filled_with(&full, y_size * z_size, x_size),
+            z_size,
+        )
+    }
+
+    fn cell(&self, pos: Coord) -> usize {
+        pos.y * self.cells.row_len() + pos.x
+    }
+
+    fn support_index(&self, pos: Coord, tile: usize, dir: usize) -> usize {
+        (self.cell(pos) * self.tiles + tile) * self.sides + dir
+    }
+
+    /// Everything removed since the last call, leaving an empty diff behind.
+    pub fn take_diff(&mut self) -> Diff {
+        mem::take(&mut self.diff)
+    }
+
+    /// Puts back what `diff` removed, returning the wave to the state before it.
+    pub fn undo(&mut self, params: &Params, diff: &Diff) {
+        for (pos, removed) in diff.removed.iter().rev() {
+            self.cells.insert(pos.y, pos.x, removed);
+        }
+        self.restore(params, diff);
+    }
+
+    /// Removes again what `diff` removed from the state before it.
+    pub fn redo(&mut self, params: &Params, diff: &Diff) {
+        for (pos, removed) in &diff.removed {
+            self.cells.remove(pos.y, pos.x, removed);
+        }
+        self.restore(params, diff);
+    }
+
+    /// Brings the counts and sums of the cells `diff` changed back in line with their
+    /// candidates.
+    fn restore(&mut self, params: &Params, diff: &Diff) {
+        for pos in diff.cells() {
+            self.recount(params, pos);
+            self.reweigh(params, pos);
+            self.reweigh_neighbors(params, pos);
+        }
+    }
+
+    /// Counts again how many candidates of `pos` support the tiles of each neighbor, so
+    /// changes need no log of every count they lowered.
+    fn recount(&mut self, params: &Params, pos: Coord) {
+        for dir in 0..self.sides {
+            let other = match neighbor(params, &self.cells, self.layers, pos, dir) {
+                Some(other) => other,
+                None => continue,
+            };
+            let back = params.model.opposite(dir);
+            let candidates = self.cells.get(pos.y, pos.x).unwrap();
+            for tile in 0..self.tiles {
+                let index = self.support_index(other, tile, back);
+                self.support[index] =
+                    params.model.compatible()[back][tile].common_len(candidates) as u32;
+            }
+        }
+    }
+
+    /// Sums the weights of the candidates of `pos` again.
+    fn reweigh(&mut self, params: &Params, pos: Coord) {
+        let cell = self.cell(pos);
+        self.sums[cell] = Sums::of(params, &self.cells, pos);
+    }
+
+    /// Sums the weights of the neighbors of `pos` again after it was decided or undecided,
+    /// as a learned model weighs tiles by their decided neighbors.
+    fn reweigh_neighbors(&mut self, params: &Params, pos: Coord) {
+        if params.model.adjacency().is_none() {
+            return;
+        }
+        for dir in 0..self.sides {
+            if let Some(other) = neighbor(params, &self.cells, self.layers, pos, dir) {
+                self.reweigh(params, other);
+            }
+        }
+    }
+}
+
+/// Collapses the whole wave by repeatedly observing the cell with the lowest entropy
+/// and propagating the choice. When a cell runs out of candidates the wave is reset to
+/// the last observation, whose choice is banned before trying again.
+/// The same `seed` always collapses the same wave identically.
+/// Returns the last contradiction if it could not be resolved within `backtracking`.
+pub fn solve(
+    params: &Params,
+    wave: &mut Wave,
+    seed: u64,
+    backtracking: &Backtracking,
+) -> Result<(), Contradiction> {
+    let mut generator = Generator::new(params, wave.clone(), seed, *backtracking);
+    let result = generator.by_ref().try_for_each(|step| step.map(drop));
+    *wave = generator.into_wave();
+    result
 }
 
-fn find_neighbors<'p>(
-    params: &'p Params<'p>,
-    wave: &Array2D<Vec<&'p Field>>,
+/// Removes `tile` from the candidates at `pos` and propagates the removal.
+pub(crate) fn ban(
+    params: &Params,
+    wave: &mut Wave,
     pos: Coord,
-) -> [Vec<&'p str>; 4] {
-    let mut offsets = [2, 3, 0, 1].iter();
-    [
-        {
-            let y = pos.y as i32 - 1;
-            if y < 0 {
-                params.sides[0].get(pos.x).unwrap()
-            } else {
-                wave.get(y as usize, pos.x).unwrap()
+    tile: usize,
+) -> Result<(), Contradiction> {
+    let mut remaining = wave
+        .cells
+        .get(pos.y, pos.x)
+        .expect("banned coord should be in wave")
+        .to_set();
+    remaining.remove(tile);
+    update_field(params, wave, pos, &remaining).map(|_| ())
+}
+
+/// The undecided cell whose candidates have the lowest Shannon entropy of their weights,
+/// or `None` once every cell is decided.
+pub(crate) fn lowest_entropy<R: Rng + ?Sized>(wave: &Wave, rng: &mut R) -> Option<Coord> {
+    let row_len = wave.cells.row_len();
+    wave.sums
+        .iter()
+        .enumerate()
+        .filter(|(_, sums)| sums.count > 1)
+        // a little noise breaks ties without always favoring the top left corner
+        .map(|(cell, sums)| (cell, sums.entropy() + rng.gen::<f64>() * 1e-6))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(cell, _)| Coord::new(cell % row_len, cell / row_len))
+}
+
+/// One of the candidates at `pos`, picked at random by their weights.
+pub(crate) fn observe<R: Rng>(params: &Params, cells: &Cells, pos: Coord, rng: &mut R) -> usize {
+    let candidates = cells
+        .get(pos.y, pos.x)
+        .expect("observed coord should be in wave")
+        .iter()
+        .collect_vec();
+    *candidates
+        .choose_weighted(rng, |&tile| params.weight(tile, pos, cells))
+        .or_else(|_| candidates.choose(rng).ok_or(()))
+        .expect("observed entry should not be empty")
+}
+
+/// Restricts the field at `pos` to `tiles` and propagates the change through the wave.
+/// Returns the number of updated fields or the first field that ran out of candidates.
+pub(crate) fn update_field(
+    params: &Params,
+    wave: &mut Wave,
+    pos: Coord,
+    tiles: &TileSet,
+) -> Result<usize, Contradiction> {
+    let removed = wave
+        .cells
+        .get(pos.y, pos.x)
+        .expect("updated coord should be in wave")
+        .difference(tiles);
+    propagate(params, wave, vec![(pos, removed)])
+}
+
+/// Restricts the top left cells of the wave to the tiles of a partial map, e.g. to place
+/// hand-made landmarks, and propagates each restriction. Fails if the map is larger than
+/// the wave or its constraints cannot all hold.
+pub fn constrain(
+    params: &Params,
+    wave: &mut Wave,
+    constraints: &Array2D<TileSet>,
+) -> Result<usize, String> {
+    if constraints.row_len() > wave.cells.row_len()
+        || constraints.column_len() > wave.cells.column_len()
+    {
+        return Err(format!(
+            "a map of {}x{} constraints does not fit in {}x{} cells",
+            constraints.row_len(),
+            constraints.column_len(),
+            wave.cells.row_len(),
+            wave.cells.column_len()
+        ));
+    }
+    let mut updates = 0;
+    for ((y, x), tiles) in constraints.enumerate_row_major() {
+        if tiles.len() < params.model.tile_count() {
+            updates += update_field(params, wave, Coord::new(x, y), tiles)
+                .map_err(|contradiction| contradiction.to_string())?;
+        }
+    }
+    Ok(updates)
+}
+
+/// Removes every candidate that is not supported by all of its neighbors and
+/// propagates the removals, e.g. to apply the border constraints to a fresh wave.
+pub(crate) fn update_wave(params: &Params, wave: &mut Wave) -> Result<usize, Contradiction> {
+    let mut unsupported = Vec::new();
+    for (y, x) in wave.cells.indices_row_major() {
+        let pos = Coord::new(x, y);
+        let mut removed = TileSet::empty(wave.tiles);
+        for tile in wave.cells.get(y, x).unwrap().iter() {
+            if (0..wave.sides).any(|dir| wave.support[wave.support_index(pos, tile, dir)] == 0) {
+                removed.insert(tile);
             }
-        },
-        {
-            let x = pos.x + 1;
-            if x >= wave.column_len() {
-                params.sides[1].get(pos.y).unwrap()
-            } else {
-                wave.get(pos.y, x).unwrap()
+        }
+        if !removed.is_empty() {
+            unsupported.push((pos, removed));
+        }
+    }
+    propagate(params, wave, unsupported)
+}
+
+fn propagate(
+    params: &Params,
+    wave: &mut Wave,
+    changes: Vec<(Coord, TileSet)>,
+) -> Result<usize, Contradiction> {
+    for (pos, removed) in changes {
+        if let Err(contradiction) = remove(params, wave, pos, &removed) {
+            return Err(abandon(params, wave, contradiction));
+        }
+    }
+
+    let mut updates: usize = 0;
+    let row_len = wave.cells.row_len();
+    while let Some((from, gone)) = wave.worklist.pop(row_len) {
+        for dir in 0..wave.sides {
+            let pos = match neighbor(params, &wave.cells, wave.layers, from, dir) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let back = params.model.opposite(dir);
+            let mut removed = TileSet::empty(wave.tiles);
+            for tile in gone
+                .iter()
+                .flat_map(|gone| params.model.compatible()[dir][gone].iter())
+            {
+                let index = wave.support_index(pos, tile, back);
+                wave.support[index] -= 1;
+                if wave.support[index] == 0 && wave.cells.get(pos.y, pos.x).unwrap().contains(tile)
+                {
+                    removed.insert(tile);
+                }
             }
-        },
-        {
-            let y = pos.y + 1;
-            if y >= wave.row_len() {
-                params.sides[2].get(pos.x).unwrap()
-            } else {
-                wave.get(y, pos.x).unwrap()
+            if removed.is_empty() {
+                continue;
             }
-        },
-        {
-            let x = pos.x as i32 - 1;
-            if x < 0 {
-                params.sides[3].get(pos.x).unwrap()
-            } else {
-                wave.get(pos.y, x as usize).unwrap()
+            updates += 1;
+            if let Err(contradiction) = remove(params, wave, pos, &removed) {
+                return Err(abandon(params, wave, contradiction));
             }
-        },
-    ]
-    .map(|side: &Vec<&Field>| {
-        let offset = offsets.next().unwrap();
-        let mut res = side.iter().map(|f| f.sides[*offset].as_str()).collect_vec();
-        res.clear_duplicates();
-        res
-    })
+        }
+    }
+    Ok(updates)
+}
+
+/// Stops propagating after `contradiction`, counting the support of every cell changed
+/// since the last diff again so it matches the candidates left.
+fn abandon(params: &Params, wave: &mut Wave, contradiction: Contradiction) -> Contradiction {
+    wave.worklist.clear(wave.cells.row_len());
+    wave.diff
+        .cells()
+        .into_iter()
+        .for_each(|pos| wave.recount(params, pos));
+    contradiction
 }
 
-fn find_real_neighbors(wave: &Array2D<Vec<&Field>>, pos: Coord) -> Vec<Coord> {
-    let mut neighbors = Vec::with_capacity(4);
-    if pos.y > 0 {
-        neighbors.push(Coord::new(pos.x, pos.y - 1));
+/// Takes `removed` out of the candidates at `pos` and queues the cell for propagation.
+fn remove(
+    params: &Params,
+    wave: &mut Wave,
+    pos: Coord,
+    removed: &TileSet,
+) -> Result<(), Contradiction> {
+    if removed.is_empty() {
+        return Ok(());
+    }
+    let cell = wave.cell(pos);
+    let decided = wave.sums[cell].count == 1;
+    for tile in removed.iter() {
+        let weight = params.weight(tile, pos, &wave.cells);
+        wave.sums[cell].subtract(weight);
     }
-    if pos.x < wave.row_len() - 1 {
-        neighbors.push(Coord::new(pos.x + 1, pos.y));
+    wave.cells.remove(pos.y, pos.x, removed);
+    wave.diff.removed.push((pos, removed.clone()));
+    if decided != (wave.sums[cell].count == 1) {
+        wave.reweigh_neighbors(params, pos);
     }
-    if pos.y < wave.column_len() - 1 {
-        neighbors.push(Coord::new(pos.x, pos.y + 1));
+    if wave.cells.get(pos.y, pos.x).unwrap().is_empty() {
+        return Err(contradiction(params, wave, pos, removed));
     }
-    if pos.x > 0 {
-        neighbors.push(Coord::new(pos.x - 1, pos.y));
+    wave.worklist.push(cell, pos, removed);
+    Ok(())
+}
+
+fn contradiction(params: &Params, wave: &Wave, pos: Coord, removed: &TileSet) -> Contradiction {
+    let sides = (0..wave.sides)
+        .map(
+            |dir| match neighbor(params, &wave.cells, wave.layers, pos, dir) {
+                Some(other) => wave
+                    .cells
+                    .get(other.y, other.x)
+                    .unwrap()
+                    .iter()
+                    .map(|tile| {
+                        params
+                            .model
+                            .label(tile, params.model.opposite(dir))
+                            .to_string()
+                    })
+                    .unique()
+                    .collect_vec(),
+                None => edge(params, &wave.cells, wave.layers, &wave.edges, pos, dir)
+                    .labels(params.model, dir),
+            },
+        )
+        .collect_vec();
+    Contradiction::new(
+        pos,
+        sides,
+        removed
+            .iter()
+            .map(|tile| params.fields[tile].clone())
+            .collect(),
+    )
+}
+
+/// The boundary beyond the edge of the grid next to `pos` in direction `dir`, on the
+/// top or bottom edge if the step leaves the rows and else on the left or right edge.
+/// Above and below a volume everything is free.
+fn edge<'e>(
+    params: &Params,
+    cells: &Cells,
+    layers: usize,
+    edges: &'e [Vec<Boundary>; 4],
+    pos: Coord,
+    dir: usize,
+) -> &'e Boundary {
+    const FREE: &Boundary = &Boundary::Free;
+    let rows = cells.column_len() / layers;
+    let (x, row, layer) = step(params, rows, pos, dir);
+    if row < 0 {
+        &edges[0][pos.x]
+    } else if row >= rows as isize {
+        &edges[2][pos.x]
+    } else if x < 0 {
+        &edges[3][pos.y]
+    } else if x >= cells.row_len() as isize {
+        &edges[1][pos.y]
+    } else {
+        debug_assert!(layer < 0 || layer >= layers as isize);
+        FREE
     }
-    neighbors
 }
 
-fn fits(a: &str, b: &str) -> bool {
+/// The x, row within the layer and layer one step from `pos` in direction `dir`, which
+/// may lie outside the grid.
+fn step(params: &Params, rows: usize, pos: Coord, dir: usize) -> (isize, isize, isize) {
+    let (layer, row) = (pos.y / rows, pos.y % rows);
+    let (dx, dy, dz) = params.model.topology().offset(row, dir);
+    (pos.x as isize + dx, row as isize + dy, layer as isize + dz)
+}
+
+/// The coord next to `pos` in direction `dir` if it is in the grid.
+/// A periodic grid always has a neighbor, wrapping around at the edges.
+pub(crate) fn neighbor(
+    params: &Params,
+    cells: &Cells,
+    layers: usize,
+    pos: Coord,
+    dir: usize,
+) -> Option<Coord> {
+    let rows = cells.column_len() / layers;
+    let (x, row, layer) = step(params, rows, pos, dir);
+    let wrap = |i: isize, size: usize| {
+        if params.periodic {
+            Some(i.rem_euclid(size as isize) as usize)
+        } else {
+            usize::try_from(i).ok().filter(|&i| i < size)
+        }
+    };
+    let (x, row, layer) = (
+        wrap(x, cells.row_len())?,
+        wrap(row, rows)?,
+        wrap(layer, layers)?,
+    );
+    Some(Coord::new(x, layer * rows + row))
+}
+
+/// Whether the side labels `a` and `b` may face each other: `i` labels fit the same
+/// `i` label and `p` labels the `q` label of the same name, unless both carry the same
+/// `u_` flag, e.g. `i-Track-u_skew`.
+pub fn fits(a: &str, b: &str) -> bool {
     let av = a.split('-').collect_vec();
     let bv = b.split('-').collect_vec();
 
@@ -166,15 +903,20 @@ fn fits(a: &str, b: &str) -> bool {
     false
 }
 
-fn print_wave(wave: &Array2D<Vec<&Field>>) {
+/// Prints the candidates of every cell, one row per line.
+pub fn print_wave(fields: &[Field], wave: &Cells) {
     wave.rows_iter()
-        .for_each(|r| println!("{}", r.map(|f| entry_string(f)).join(", ")));
+        .for_each(|r| println!("{}", r.map(|f| entry_string(fields, f)).join(", ")));
 }
 
-pub fn entry_string(entry: &Vec<&Field>) -> String {
+/// The image names of the candidates in `entry`, e.g. `[track.png, wire.png]`.
+pub fn entry_string(fields: &[Field], entry: Tiles) -> String {
     format!(
         "[{}]",
-        entry.iter().map(|field| field.img_name.as_str()).join(", ")
+        entry
+            .iter()
+            .map(|tile| fields[tile].img_name.as_str())
+            .join(", ")
     )
 }
 
@@ -0,0 +1,362 @@
+use std::path::Path;
+
+use array2d::Array2D;
+use itertools::Itertools;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use super::*;
+use crate::fixtures::{colors, field, free_borders, stripes};
+use crate::parser;
+
+fn all(fields: &[Field]) -> TileSet {
+    TileSet::full(fields.len())
+}
+
+fn collapsed(entry: Tiles) -> usize {
+    entry
+        .iter()
+        .exactly_one()
+        .ok()
+        .expect("entry should be collapsed")
+}
+
+#[test]
+fn fits_identical_and_mirrored_sides() {
+    assert!(fits("i-Track", "i-Track"));
+    assert!(fits("p-Component", "q-Component"));
+    assert!(!fits("p-Component", "p-Component"));
+    assert!(!fits("i-Track", "i-Wire"));
+    assert!(!fits("i-Track-u_skew", "i-Track-u_skew"));
+    assert!(!fits("n-Track", "n-Track"));
+}
+
+#[test]
+fn entropy_prefers_dominant_weights() {
+    let sums = |weights: &[f64]| {
+        let mut sums = Sums::default();
+        weights.iter().for_each(|&weight| sums.add(weight));
+        sums
+    };
+    assert!(sums(&[9.0, 1.0]).entropy() < sums(&[1.0, 1.0]).entropy());
+    assert_eq!(sums(&[1.0]).entropy(), 0.0);
+
+    let mut removed = sums(&[9.0, 1.0, 3.0]);
+    removed.subtract(3.0);
+    assert_eq!(removed.count, 2);
+    assert!((removed.entropy() - sums(&[9.0, 1.0]).entropy()).abs() < 1e-12);
+}
+
+#[test]
+fn sums_follow_propagation_and_undo() {
+    let set = parser::load(Path::new("res/circuit_example.json")).unwrap();
+    let borders = free_borders();
+    let model = set.model();
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let fresh = Wave::filled(&params, 6, 6).unwrap();
+    let matches = |wave: &Wave| {
+        wave.cells.indices_row_major().all(|(y, x)| {
+            let pos = Coord::new(x, y);
+            let expected = Sums::of(&params, &wave.cells, pos);
+            let sums = wave.sums[wave.cell(pos)];
+            sums.count == expected.count
+                && (sums.weight - expected.weight).abs() < 1e-9
+                && (sums.log_weight - expected.log_weight).abs() < 1e-9
+        })
+    };
+
+    let mut wave = fresh.clone();
+    let mut rng = SmallRng::seed_from_u64(4);
+    let pos = lowest_entropy(&wave, &mut rng).unwrap();
+    let tile = observe(&params, &wave.cells, pos, &mut rng);
+    update_field(
+        &params,
+        &mut wave,
+        pos,
+        &TileSet::single(model.tile_count(), tile),
+    )
+    .unwrap();
+    assert!(matches(&wave));
+    let diff = wave.take_diff();
+    wave.undo(&params, &diff);
+    assert!(matches(&wave));
+    wave.redo(&params, &diff);
+    assert!(matches(&wave));
+}
+
+#[test]
+fn update_field_reports_contradiction() {
+    let fields = stripes();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let cells = Array2D::from_rows(&[vec![all(&fields), TileSet::single(2, 0)]]).unwrap();
+    let mut wave = Wave::new(&params, Cells::from(&cells)).unwrap();
+
+    let chosen = TileSet::single(2, 0);
+    let contradiction = update_field(&params, &mut wave, Coord::new(0, 0), &chosen).unwrap_err();
+    assert_eq!(contradiction.pos(), &Coord::new(1, 0));
+    assert_eq!(contradiction.removed(), &vec![fields[0].clone()]);
+    assert_eq!(contradiction.sides()[3], vec!["i-B".to_string()]);
+    assert!(wave.cells().get(0, 1).unwrap().is_empty());
+    assert!(wave.worklist.queue.is_empty());
+    assert!(wave.worklist.queued.iter().all(|&queued| !queued));
+}
+
+#[test]
+fn update_field_propagates_along_the_row() {
+    let fields = stripes();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let mut wave = Wave::filled(&params, 3, 1).unwrap();
+
+    let chosen = TileSet::single(2, 0);
+    assert_eq!(
+        update_field(&params, &mut wave, Coord::new(0, 0), &chosen),
+        Ok(2)
+    );
+    let row = wave
+        .cells()
+        .elements_row_major_iter()
+        .map(collapsed)
+        .collect_vec();
+    assert_eq!(row, vec![0, 1, 0]);
+}
+
+#[test]
+fn solve_collapses_every_cell_consistently() {
+    let fields = stripes();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let mut wave = Wave::filled(&params, 4, 4).unwrap();
+
+    assert!(solve(&params, &mut wave, 0, &Backtracking::default()).is_ok());
+    assert!(wave
+        .cells()
+        .elements_row_major_iter()
+        .all(|entry| entry.len() == 1));
+    for (y, x) in wave.cells().indices_row_major() {
+        if x + 1 < wave.cells().row_len() {
+            let left = collapsed(wave.cells().get(y, x).unwrap());
+            let right = collapsed(wave.cells().get(y, x + 1).unwrap());
+            assert_ne!(fields[left].img_name(), fields[right].img_name());
+        }
+    }
+}
+
+#[test]
+fn solve_circuit_set() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let borders = free_borders();
+    let model = Model::new(set.fields());
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let mut wave = Wave::filled(&params, 8, 8).unwrap();
+
+    solve(&params, &mut wave, 7, &Backtracking::default()).unwrap();
+    for (y, x) in wave.cells().indices_row_major() {
+        let tile = collapsed(wave.cells().get(y, x).unwrap());
+        for dir in 0..4 {
+            if let Some(other) = neighbor(&params, wave.cells(), 1, Coord::new(x, y), dir) {
+                let other = collapsed(wave.cells().get(other.y, other.x).unwrap());
+                assert!(model.compatible()[dir][tile].contains(other));
+            }
+        }
+    }
+}
+
+#[test]
+fn backtracking_resolves_contradictions() {
+    // three colors that may not touch themselves dead end every now and then
+    let fields = colors();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let mut failed_without = 0;
+    for seed in 0..40 {
+        let mut wave = Wave::filled(&params, 8, 8).unwrap();
+        if solve(&params, &mut wave, seed, &Backtracking::new(0, 0)).is_err() {
+            failed_without += 1;
+        }
+
+        let mut wave = Wave::filled(&params, 8, 8).unwrap();
+        assert!(solve(&params, &mut wave, seed, &Backtracking::default()).is_ok());
+        for (y, x) in wave.cells().indices_row_major() {
+            let field = collapsed(wave.cells().get(y, x).unwrap());
+            if let Some(right) = wave.cells().get(y, x + 1) {
+                assert_ne!(field, collapsed(right));
+            }
+            if let Some(below) = wave.cells().get(y + 1, x) {
+                assert_ne!(field, collapsed(below));
+            }
+        }
+    }
+    assert!(failed_without > 0);
+}
+
+#[test]
+fn same_seed_same_wave() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let borders = free_borders();
+    let model = Model::new(set.fields());
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let collapse = |seed| {
+        let mut wave = Wave::filled(&params, 12, 12).unwrap();
+        let result = solve(&params, &mut wave, seed, &Backtracking::default());
+        (result, wave)
+    };
+
+    assert_eq!(collapse(42), collapse(42));
+    assert_ne!(collapse(42).1, collapse(43).1);
+}
+
+#[test]
+fn periodic_wraps_around_the_edges() {
+    let fields = stripes();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, true);
+
+    // alternating columns only close up around an even width
+    let mut wave = Wave::filled(&params, 3, 2).unwrap();
+    assert!(solve(&params, &mut wave, 0, &Backtracking::default()).is_err());
+
+    let mut wave = Wave::filled(&params, 4, 2).unwrap();
+    assert!(solve(&params, &mut wave, 0, &Backtracking::default()).is_ok());
+    for y in 0..2 {
+        let first = collapsed(wave.cells().get(y, 0).unwrap());
+        let last = collapsed(wave.cells().get(y, 3).unwrap());
+        assert_ne!(first, last);
+    }
+}
+
+#[test]
+fn diff_undoes_and_redoes_a_propagation() {
+    let fields = stripes();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let fresh = Wave::filled(&params, 3, 2).unwrap();
+    let mut wave = fresh.clone();
+
+    update_field(&params, &mut wave, Coord::new(1, 1), &TileSet::single(2, 0)).unwrap();
+    let diff = wave.take_diff();
+    let updated = wave.clone();
+    assert_eq!(
+        diff.cells(),
+        vec![Coord::new(1, 1), Coord::new(2, 1), Coord::new(0, 1)]
+    );
+    wave.undo(&params, &diff);
+    assert_eq!(wave, fresh);
+    wave.redo(&params, &diff);
+    assert_eq!(wave, updated);
+}
+
+#[test]
+fn constrained_cells_are_kept_by_solve() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let borders = free_borders();
+    let model = Model::new(set.fields());
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let constraints =
+        parser::load_constraints(&set, Path::new("res/circuit_landmark.json")).unwrap();
+    let mut wave = Wave::filled(&params, 8, 8).unwrap();
+
+    constrain(&params, &mut wave, &constraints).unwrap();
+    solve(&params, &mut wave, 3, &Backtracking::default()).unwrap();
+    for ((y, x), tiles) in constraints.enumerate_row_major() {
+        assert!(tiles.contains(collapsed(wave.cells().get(y, x).unwrap())));
+    }
+
+    let mut small = Wave::filled(&params, 4, 4).unwrap();
+    assert!(constrain(&params, &mut small, &constraints).is_err());
+}
+
+#[test]
+fn weight_maps_bias_tiles_by_position() {
+    let fields = vec![field("a", ["i-A"; 4]), field("b", ["i-A"; 4])];
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let mut weights = Weights::new(2);
+    weights.set(0, Array2D::from_rows(&[vec![0.0, 1.0]]).unwrap());
+    weights.set(1, Array2D::from_rows(&[vec![1.0, 0.0]]).unwrap());
+    let params = Params::new(&fields, &model, &borders, false).with_weights(Some(&weights));
+    let mut wave = Wave::filled(&params, 4, 3).unwrap();
+
+    solve(&params, &mut wave, 0, &Backtracking::default()).unwrap();
+    for ((_, x), entry) in wave.cells().enumerate_row_major() {
+        assert_eq!(collapsed(entry), if x < 2 { 1 } else { 0 });
+    }
+}
+
+/// Removes every candidate without a fitting candidate in some neighbor until nothing
+/// changes, comparing side labels like the propagation before support counts.
+fn reference_propagation(params: &Params, cells: &mut Array2D<TileSet>) {
+    let model = params.model();
+    // only the size of the grid matters for finding neighbors
+    let grid = Cells::from(&*cells);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (y, x) in cells.indices_row_major().collect_vec() {
+            let pos = Coord::new(x, y);
+            let mut kept = cells[(y, x)].clone();
+            for tile in cells[(y, x)].iter() {
+                let supported = (0..4).all(|dir| {
+                    neighbor(params, &grid, 1, pos, dir).is_none_or(|other| {
+                        cells[(other.y, other.x)].iter().any(|candidate| {
+                            fits(
+                                model.label(tile, dir),
+                                model.label(candidate, model.opposite(dir)),
+                            )
+                        })
+                    })
+                });
+                if !supported {
+                    kept.remove(tile);
+                    changed = true;
+                }
+            }
+            cells[(y, x)] = kept;
+        }
+    }
+}
+
+#[test]
+fn support_counts_match_per_neighbor_propagation() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let borders = free_borders();
+    let model = Model::new(set.fields());
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let tiles = set.fields().len();
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut consistent = 0;
+    for _ in 0..40 {
+        let density = rng.gen_range(0.02..0.3);
+        let mut cells = Array2D::filled_with(TileSet::full(tiles), 6, 6);
+        for (y, x) in cells.indices_row_major().collect_vec() {
+            if rng.gen_bool(0.5) {
+                let mut partial = TileSet::empty(tiles);
+                (0..tiles)
+                    .filter(|_| rng.gen_bool(density))
+                    .for_each(|tile| partial.insert(tile));
+                partial.insert(rng.gen_range(0..tiles));
+                cells[(y, x)] = partial;
+            }
+        }
+        let mut wave = Wave::new(&params, Cells::from(&cells)).unwrap();
+        let result = update_wave(&params, &mut wave);
+        reference_propagation(&params, &mut cells);
+
+        if cells.elements_row_major_iter().any(TileSet::is_empty) {
+            assert!(result.is_err());
+        } else {
+            assert!(result.is_ok());
+            assert_eq!(wave.cells(), &Cells::from(&cells));
+            consistent += 1;
+        }
+    }
+    // both outcomes are covered
+    assert!(consistent > 0 && consistent < 40);
+}
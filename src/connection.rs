@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+
+use getset::Getters;
+
+use crate::collapse::{neighbor, update_field, Contradiction, Coord, Field, Params, Reason, Wave};
+use crate::tileset::TileSet;
+
+/// Requires every cell with a side labeled `label`, e.g. `Track` for `i-Track` or
+/// `p-Track-u_skew`, to form one network connected through such sides, which also
+/// reaches all `points`.
+#[derive(Clone, PartialEq, Eq, Debug, Getters)]
+#[getset(get = "pub")]
+pub struct Connection {
+    /// The name of the side label, without its type and flags.
+    label: String,
+    /// Per direction, the tiles whose side in that direction carries the label.
+    sides: Vec<TileSet>,
+    /// The tiles with the label on any side.
+    tiles: TileSet,
+    /// The cells that have to be part of the network.
+    points: Vec<Coord>,
+}
+
+impl Connection {
+    /// The network of the sides of `fields` named `label`, reaching all `points`.
+    pub fn new(fields: &[Field], label: &str, points: Vec<Coord>) -> Connection {
+        let side_count = fields.first().map_or(0, |field| field.sides().len());
+        let mut sides = vec![TileSet::empty(fields.len()); side_count];
+        let mut tiles = TileSet::empty(fields.len());
+        for (tile, field) in fields.iter().enumerate() {
+            for (dir, side) in field.sides().iter().enumerate() {
+                if side.split('-').nth(1) == Some(label) {
+                    sides[dir].insert(tile);
+                    tiles.insert(tile);
+                }
+            }
+        }
+        Connection {
+            label: label.to_string(),
+            sides,
+            tiles,
+            points,
+        }
+    }
+}
+
+/// Keeps the networks of `params` connected after a change at `pos`: the points are
+/// restricted to network tiles, and cells that can no longer reach the cells that
+/// certainly belong to the network lose their network tiles. Fails at `pos` if the
+/// network is already split. Returns whether any cell had to be restricted.
+pub(crate) fn enforce(params: &Params, wave: &mut Wave, pos: Coord) -> Result<bool, Contradiction> {
+    let mut restricted = false;
+    for connection in params.connections().iter() {
+        for &point in &connection.points {
+            let cell = wave.cells().get(*point.y(), *point.x()).unwrap();
+            if !cell.difference(&connection.tiles).is_empty() {
+                update_field(params, wave, point, &connection.tiles)?;
+                restricted = true;
+            }
+        }
+        while prune(params, wave, pos, connection)? {
+            restricted = true;
+        }
+    }
+    Ok(restricted)
+}
+
+/// Returns whether any cell had to be restricted.
+fn prune(
+    params: &Params,
+    wave: &mut Wave,
+    pos: Coord,
+    connection: &Connection,
+) -> Result<bool, Contradiction> {
+    let cells = wave.cells();
+    let row_len = cells.row_len();
+    let overlaps = |pos: Coord, tiles: &TileSet| {
+        cells
+            .get(*pos.y(), *pos.x())
+            .unwrap()
+            .iter()
+            .any(|tile| tiles.contains(tile))
+    };
+    let required = cells
+        .enumerate_row_major()
+        .filter(|(_, tiles)| {
+            !tiles.is_empty() && tiles.iter().all(|tile| connection.tiles.contains(tile))
+        })
+        .map(|((y, x), _)| Coord::new(x, y))
+        .collect::<Vec<_>>();
+    let start = match required.first() {
+        Some(&start) => start,
+        None => return Ok(false),
+    };
+
+    // every cell the network could still spread to from one of its certain cells
+    let mut reached = vec![false; cells.num_elements()];
+    reached[start.y() * row_len + start.x()] = true;
+    let mut queue = VecDeque::from([start]);
+    while let Some(from) = queue.pop_front() {
+        for dir in 0..connection.sides.len() {
+            if !overlaps(from, &connection.sides[dir]) {
+                continue;
+            }
+            let to = match neighbor(params, cells, *wave.layers(), from, dir) {
+                Some(to) => to,
+                None => continue,
+            };
+            let back = params.model().opposite(dir);
+            let index = to.y() * row_len + to.x();
+            if !reached[index] && overlaps(to, &connection.sides[back]) {
+                reached[index] = true;
+                queue.push_back(to);
+            }
+        }
+    }
+    if required
+        .iter()
+        .any(|pos| !reached[pos.y() * row_len + pos.x()])
+    {
+        return Err(Contradiction::new(pos, Vec::new(), Vec::new()).with_reason(
+            Reason::Disconnected {
+                label: connection.label.clone(),
+            },
+        ));
+    }
+
+    let cut_off = cells
+        .enumerate_row_major()
+        .filter(|&((y, x), _)| !reached[y * row_len + x])
+        .map(|((y, x), _)| Coord::new(x, y))
+        .filter(|&pos| overlaps(pos, &connection.tiles))
+        .collect::<Vec<_>>();
+    for &cell in &cut_off {
+        let mut outside = wave.cells().get(*cell.y(), *cell.x()).unwrap().to_set();
+        connection
+            .tiles
+            .iter()
+            .for_each(|tile| outside.remove(tile));
+        update_field(params, wave, cell, &outside)?;
+    }
+    Ok(!cut_off.is_empty())
+}
+
+#[cfg(test)]
+mod connection_test;
@@ -0,0 +1,177 @@
+use super::*;
+use crate::boundary::{Border, Boundary};
+use crate::collapse::{solve, Backtracking};
+use crate::generator::Generator;
+use crate::limit::Limit;
+use crate::model::Model;
+
+/// Empty cells and every rotation of road ends, straights, corners and junctions.
+fn roads() -> Vec<Field> {
+    let shapes = [
+        ("empty", [false; 4]),
+        ("end", [true, false, false, false]),
+        ("straight", [true, false, true, false]),
+        ("corner", [true, true, false, false]),
+        ("t", [true, true, true, false]),
+        ("cross", [true; 4]),
+    ];
+    let mut fields: Vec<Field> = Vec::new();
+    for (name, shape) in shapes {
+        for step in 0..4 {
+            let sides = (0..4)
+                .map(|dir| {
+                    if shape[(dir + 4 - step) % 4] {
+                        "i-Road"
+                    } else {
+                        "i-Empty"
+                    }
+                })
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            if !fields.iter().any(|field| *field.sides() == sides) {
+                fields.push(Field::new(name.to_string(), step as i32 * 90, sides, 1));
+            }
+        }
+    }
+    fields
+}
+
+/// The number of separate road networks.
+fn networks(fields: &[Field], wave: &Wave) -> usize {
+    let cells = wave.cells();
+    let tile = |x: usize, y: usize| cells.get(y, x).unwrap().iter().next().unwrap();
+    let road = |x: usize, y: usize, dir: usize| fields[tile(x, y)].sides()[dir] == "i-Road";
+    let mut seen = vec![vec![false; cells.row_len()]; cells.column_len()];
+    let mut count = 0;
+    for (y, x) in cells.indices_row_major() {
+        if seen[y][x] || !(0..4).any(|dir| road(x, y, dir)) {
+            continue;
+        }
+        count += 1;
+        let mut stack = vec![(x, y)];
+        seen[y][x] = true;
+        while let Some((x, y)) = stack.pop() {
+            let around = [
+                (x, y.wrapping_sub(1)),
+                (x + 1, y),
+                (x, y + 1),
+                (x.wrapping_sub(1), y),
+            ];
+            for (dir, (nx, ny)) in around.into_iter().enumerate() {
+                if nx < cells.row_len()
+                    && ny < cells.column_len()
+                    && road(x, y, dir)
+                    && !seen[ny][nx]
+                {
+                    seen[ny][nx] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+    }
+    count
+}
+
+#[test]
+fn road_labels_select_tiles_per_side() {
+    let fields = roads();
+    let connection = Connection::new(&fields, "Road", vec![]);
+
+    assert_eq!(fields.len(), 16);
+    assert_eq!(connection.tiles().len(), 15);
+    assert!(!connection.tiles().contains(0));
+    assert_eq!(connection.sides()[0].len(), 8);
+}
+
+#[test]
+fn connected_roads_form_one_network() {
+    let fields = roads();
+    let borders = [(); 4].map(|_| Border::All(Boundary::Side("i-Empty".to_string())));
+    let model = Model::new(&fields);
+    let connections = [Connection::new(&fields, "Road", vec![Coord::new(0, 3)])];
+    let free = Params::new(&fields, &model, &borders, false);
+    let connected = Params::new(&fields, &model, &borders, false).with_connections(&connections);
+
+    let mut fragmented = 0;
+    for seed in 0..20 {
+        let mut wave = Wave::filled(&free, 8, 8).unwrap();
+        solve(&free, &mut wave, seed, &Backtracking::default()).unwrap();
+        if networks(&fields, &wave) > 1 {
+            fragmented += 1;
+        }
+
+        let mut wave = Wave::filled(&connected, 8, 8).unwrap();
+        solve(&connected, &mut wave, seed, &Backtracking::default()).unwrap();
+        assert_eq!(networks(&fields, &wave), 1);
+        assert!(connections[0]
+            .tiles()
+            .contains(wave.cells().get(3, 0).unwrap().iter().next().unwrap()));
+    }
+    assert!(fragmented > 0);
+}
+
+#[test]
+fn split_network_contradicts() {
+    let fields = roads();
+    let borders = [(); 4].map(|_| Border::All(Boundary::Side("i-Empty".to_string())));
+    let model = Model::new(&fields);
+    let connections = [Connection::new(
+        &fields,
+        "Road",
+        vec![Coord::new(0, 0), Coord::new(2, 0)],
+    )];
+    let params = Params::new(&fields, &model, &borders, false).with_connections(&connections);
+    let mut wave = Wave::filled(&params, 3, 2).unwrap();
+    for y in 0..2 {
+        let empty = TileSet::single(fields.len(), 0);
+        update_field(&params, &mut wave, Coord::new(1, y), &empty).unwrap();
+    }
+    let contradiction = enforce(&params, &mut wave, Coord::new(1, 1)).unwrap_err();
+
+    assert_eq!(
+        contradiction.reason(),
+        &Reason::Disconnected {
+            label: "Road".to_string()
+        }
+    );
+    assert_eq!(
+        contradiction.to_string(),
+        "after field 1, 1 the Road network is split"
+    );
+}
+
+#[test]
+fn points_outside_the_grid_are_rejected() {
+    let fields = roads();
+    let borders = [(); 4].map(|_| Border::free());
+    let model = Model::new(&fields);
+    let connections = [Connection::new(&fields, "Road", vec![Coord::new(3, 1)])];
+    let params = Params::new(&fields, &model, &borders, false).with_connections(&connections);
+
+    assert_eq!(
+        Wave::filled(&params, 3, 2).unwrap_err(),
+        "the Road connection point 3, 1 is outside the 3x2 grid"
+    );
+    assert!(Wave::filled(&params, 4, 2).is_ok());
+}
+
+#[test]
+fn limits_are_enforced_again_after_connections() {
+    // the point needs a road leading right, so two cells are roads and the limit
+    // then leaves no road for the third
+    let fields = roads();
+    let borders = [(); 4].map(|_| Border::All(Boundary::Side("i-Empty".to_string())));
+    let model = Model::new(&fields);
+    let connections = [Connection::new(&fields, "Road", vec![Coord::new(0, 0)])];
+    let limits = [Limit::new(connections[0].tiles().clone(), 0, 2)];
+    let params = Params::new(&fields, &model, &borders, false)
+        .with_connections(&connections)
+        .with_limits(&limits);
+    let wave = Wave::filled(&params, 3, 1).unwrap();
+    let generator = Generator::new(&params, wave, 0, Backtracking::default());
+
+    assert_eq!(
+        generator.wave().cells().get(0, 2).unwrap().to_set(),
+        TileSet::single(fields.len(), 0)
+    );
+}
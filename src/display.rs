@@ -1,24 +1,89 @@
 use std::collections::HashMap;
+use std::fs;
 use std::io;
 use std::path::Path;
 
 use array2d::Array2D;
-use itertools::Itertools;
+use getset::Getters;
 use sdl2::event::Event;
-use sdl2::image::{InitFlag, LoadTexture};
+use sdl2::image::{InitFlag, LoadSurface, LoadTexture, SaveSurface};
 use sdl2::keyboard::Keycode;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
+use sdl2::surface::Surface;
 
-use crate::collapse::{entry_string, update_field, Coord, Field, Params};
-use crate::parser::Set;
+use wave_function_collapse::batch::{Batch, Report};
+use wave_function_collapse::boundary::Border;
+use wave_function_collapse::collapse::{
+    constrain, entry_string, print_wave, solve, Backtracking, Coord, Field, Params, Wave,
+};
+use wave_function_collapse::generator::{Generator, Step};
+use wave_function_collapse::heuristic::Heuristic;
+use wave_function_collapse::model::Model;
+use wave_function_collapse::overlapping::Overlapping;
+use wave_function_collapse::parser::{load_constraints, load_weights, save_map, Set};
+use wave_function_collapse::tileset::Tiles;
+use wave_function_collapse::topology::Topology;
+use wave_function_collapse::voxel::{assemble, Voxels};
+use wave_function_collapse::weights::Weights;
+use wave_function_collapse::world::World;
+
+/// How a set is generated: the seed, the edges of the grid, the limits for backtracking,
+/// optional files with a partial map of constraints and with weight maps, and the
+/// heuristic choosing the next cell.
+#[derive(new, Getters)]
+#[getset(get = "pub")]
+pub struct Options<'o> {
+    seed: u64,
+    periodic: bool,
+    borders: &'o [Border; 4],
+    backtracking: Backtracking,
+    constraints: Option<&'o Path>,
+    weights: Option<&'o Path>,
+    heuristic: &'o dyn Heuristic,
+}
+
+/// Where the cell at `x`, `y` is drawn, `img_size` apart from its neighbors in a row.
+/// Hex cells are taller than wide and every odd row is shifted by half a cell,
+/// the layers of a volume are drawn one below the other.
+fn tile_rect(topology: Topology, x: usize, y: usize, img_size: u32) -> Rect {
+    match topology {
+        Topology::Square | Topology::Voxel => Rect::new(
+            (x as u32 * img_size) as i32,
+            (y as u32 * img_size) as i32,
+            img_size,
+            img_size,
+        ),
+        Topology::Hex => {
+            let height = (img_size as f64 * 2.0 / 3f64.sqrt()).round() as u32;
+            Rect::new(
+                (x as u32 * img_size + y as u32 % 2 * img_size / 2) as i32,
+                (y as u32 * height * 3 / 4) as i32,
+                img_size,
+                height,
+            )
+        }
+    }
+}
 
 pub fn render(set: Set, wave: Array2D<Field>, json: &Path) -> Result<(), String> {
     let img_size: u32 = 14;
+    let topology = *set.topology();
+    let corner = tile_rect(
+        topology,
+        wave.row_len() - 1,
+        wave.column_len() - 1,
+        img_size,
+    );
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
     let _image_context = sdl2::image::init(InitFlag::PNG | InitFlag::JPG)?;
     let window = video_subsystem
-        .window("Wave Function Collapse", 448, 448)
+        .window(
+            "Wave Function Collapse",
+            corner.right() as u32,
+            corner.bottom() as u32,
+        )
         .position_centered()
         .build()
         .map_err(|e| e.to_string())?;
@@ -59,12 +124,7 @@ pub fn render(set: Set, wave: Array2D<Field>, json: &Path) -> Result<(), String>
         for x in 0..wave.row_len() {
             for y in 0..wave.column_len() {
                 let field = wave.get(y, x).expect("coord should be in wave");
-                let target = Rect::new(
-                    (x as u32 * img_size) as i32,
-                    (y as u32 * img_size) as i32,
-                    img_size,
-                    img_size,
-                );
+                let target = tile_rect(topology, x, y, img_size);
                 let texture = pngs
                     .get(field.img_name())
                     .expect("wave should only produce names in the set");
@@ -74,7 +134,7 @@ pub fn render(set: Set, wave: Array2D<Field>, json: &Path) -> Result<(), String>
                     target,
                     *field.rotation() as f64,
                     None,
-                    false,
+                    *field.mirrored(),
                     false,
                 )?;
             }
@@ -85,7 +145,286 @@ pub fn render(set: Set, wave: Array2D<Field>, json: &Path) -> Result<(), String>
     Ok(())
 }
 
-pub fn interactive_render(set: Set, json: &Path) -> Result<(), String> {
+pub fn auto_render(set: Set, json: &Path, options: &Options) -> Result<(), String> {
+    // wfc setup
+    let x_size = 32;
+    let y_size = 32;
+    let model = set.model();
+    let weights = load_weight_maps(&set, options)?;
+    let params = Params::new(set.fields(), &model, options.borders, options.periodic)
+        .with_weights(weights.as_ref())
+        .with_limits(set.limits())
+        .with_connections(set.connections())
+        .with_heuristic(options.heuristic);
+    let mut wave = Wave::filled(&params, x_size, y_size)?;
+    apply_constraints(&set, &params, &mut wave, options)?;
+
+    if let Err(contradiction) = solve(&params, &mut wave, options.seed, &options.backtracking) {
+        print_wave(set.fields(), wave.cells());
+        return Err(contradiction.to_string());
+    }
+    let collapsed = Array2D::from_iter_row_major(
+        wave.cells()
+            .elements_row_major_iter()
+            .map(|entry| set.fields()[entry.iter().next().unwrap()].clone()),
+        y_size,
+        x_size,
+    )
+    .map_err(|e| format!("{:?}", e))?;
+
+    render(set, collapsed, json)
+}
+
+/// Restricts `wave` to the partial map of the options, if there is one. The layers of
+/// a volume are constrained by consecutive rows of the map.
+fn apply_constraints(
+    set: &Set,
+    params: &Params,
+    wave: &mut Wave,
+    options: &Options,
+) -> Result<(), String> {
+    if let Some(path) = options.constraints {
+        constrain(params, wave, &load_constraints(set, path)?)?;
+    }
+    Ok(())
+}
+
+/// The weight maps of the options, if there are any.
+fn load_weight_maps(set: &Set, options: &Options) -> Result<Option<Weights>, String> {
+    options
+        .weights
+        .map(|path| load_weights(set, path, load_image))
+        .transpose()
+}
+
+/// Loads an image as RGBA colors, one per pixel.
+pub fn load_image(path: &Path) -> Result<Array2D<u32>, String> {
+    let _image_context = sdl2::image::init(InitFlag::PNG)?;
+    let surface = Surface::from_file(path)?.convert_format(PixelFormatEnum::RGBA8888)?;
+    let (width, height, pitch) = (
+        surface.width() as usize,
+        surface.height() as usize,
+        surface.pitch() as usize,
+    );
+    let pixels = surface.with_lock(|bytes| {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| y * pitch + x * 4))
+            .map(|i| u32::from_ne_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]))
+            .collect::<Vec<_>>()
+    });
+    Array2D::from_row_major(&pixels, height, width).map_err(|e| format!("{:?}", e))
+}
+
+/// Saves RGBA colors as a png.
+pub fn save_image(path: &Path, image: &Array2D<u32>) -> Result<(), String> {
+    let mut bytes: Vec<u8> = image
+        .elements_row_major_iter()
+        .flat_map(|color| color.to_ne_bytes())
+        .collect();
+    let width = image.row_len() as u32;
+    let surface = Surface::from_data(
+        &mut bytes,
+        width,
+        image.column_len() as u32,
+        width * 4,
+        PixelFormatEnum::RGBA8888,
+    )?;
+    surface.save(path)
+}
+
+pub fn overlapping_render(
+    sample: &Path,
+    n: usize,
+    out: &Path,
+    options: &Options,
+) -> Result<(), String> {
+    // wfc setup
+    let x_size = 32;
+    let y_size = 32;
+    let overlapping = Overlapping::new(&load_image(sample)?, n, true)?;
+    let model = Model::new(overlapping.fields());
+    let params = Params::new(
+        overlapping.fields(),
+        &model,
+        options.borders,
+        options.periodic,
+    )
+    .with_heuristic(options.heuristic);
+    let mut wave = Wave::filled(&params, x_size, y_size)?;
+
+    if let Err(contradiction) = solve(&params, &mut wave, options.seed, &options.backtracking) {
+        print_wave(overlapping.fields(), wave.cells());
+        return Err(contradiction.to_string());
+    }
+    let image = overlapping.image(wave.cells());
+    save_image(out, &image)?;
+
+    // sdl2 setup
+    let pixel_size: u32 = 14;
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let window = video_subsystem
+        .window("Wave Function Collapse", 448, 448)
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut canvas = window
+        .into_canvas()
+        .software()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    'mainloop: loop {
+        for event in sdl_context.event_pump()?.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Option::Some(Keycode::Escape),
+                    ..
+                } => break 'mainloop,
+                _ => {}
+            }
+        }
+
+        for x in 0..image.row_len() {
+            for y in 0..image.column_len() {
+                let [r, g, b, a] = image[(y, x)].to_be_bytes();
+                canvas.set_draw_color(Color::RGBA(r, g, b, a));
+                canvas.fill_rect(Rect::new(
+                    (x as u32 * pixel_size) as i32,
+                    (y as u32 * pixel_size) as i32,
+                    pixel_size,
+                    pixel_size,
+                ))?;
+            }
+        }
+        canvas.present();
+    }
+
+    Ok(())
+}
+
+/// Shows the fields around the origin of an unbounded world generated in chunks of
+/// `chunk_size`, reporting every seam position that had to be dropped.
+pub fn chunk_render(
+    set: Set,
+    json: &Path,
+    seed: u64,
+    backtracking: &Backtracking,
+    chunk_size: usize,
+) -> Result<(), String> {
+    let model = set.model();
+    let mut world = World::new(set.fields(), &model, chunk_size, seed, *backtracking)?;
+    let region = world
+        .region(-16, -16, 32, 32)
+        .map_err(|contradiction| contradiction.to_string())?;
+    for ((x, y), chunk) in world.chunks() {
+        if !chunk.broken().is_empty() {
+            println!(
+                "chunk {}, {} dropped the seam positions {:?}",
+                x,
+                y,
+                chunk.broken()
+            );
+        }
+    }
+    let collapsed = Array2D::from_iter_row_major(
+        region
+            .elements_row_major_iter()
+            .map(|&tile| set.fields()[tile].clone()),
+        32,
+        32,
+    )
+    .map_err(|e| format!("{:?}", e))?;
+
+    render(set, collapsed, json)
+}
+
+/// Solves `batch` in parallel and writes every solved wave to `out_dir`, named by its
+/// seed: a `.vox` model for voxel sets and an example map `.json` otherwise.
+/// The seed of the options is ignored in favor of the seeds of the batch.
+pub fn batch_export(
+    set: Set,
+    json: &Path,
+    batch: &Batch,
+    options: &Options,
+    out_dir: &Path,
+) -> Result<Report, String> {
+    let model = set.model();
+    let weights = load_weight_maps(&set, options)?;
+    let params = Params::new(set.fields(), &model, options.borders, options.periodic)
+        .with_weights(weights.as_ref())
+        .with_limits(set.limits())
+        .with_connections(set.connections())
+        .with_heuristic(options.heuristic);
+    let backtracking = &options.backtracking;
+    fs::create_dir_all(out_dir).map_err(|e| format!("{}: {}", out_dir.display(), e))?;
+
+    if *set.topology() == Topology::Voxel {
+        let (x_size, y_size, z_size) = (8, 8, 4);
+        let mut wave = Wave::volume(&params, x_size, y_size, z_size)?;
+        apply_constraints(&set, &params, &mut wave, options)?;
+        let path = json
+            .parent()
+            .expect("json should be in a directroy")
+            .join(set.dir());
+        let mut models = HashMap::with_capacity(set.fields().len());
+        for field in set.fields() {
+            models.insert(
+                field.img_name().clone(),
+                Voxels::load(&path.join(field.img_name()))?,
+            );
+        }
+        Ok(batch.run(&params, &wave, backtracking, |seed, wave| {
+            assemble(set.fields(), &models, wave.cells(), z_size)?
+                .save(&out_dir.join(format!("{}.vox", seed)))
+        }))
+    } else {
+        let mut wave = Wave::filled(&params, 32, 32)?;
+        apply_constraints(&set, &params, &mut wave, options)?;
+        Ok(batch.run(&params, &wave, backtracking, |seed, wave| {
+            save_map(&set, wave.cells(), &out_dir.join(format!("{}.json", seed)))
+        }))
+    }
+}
+
+/// Collapses a volume of voxel tiles into one MagicaVoxel model.
+pub fn auto_volume(set: Set, json: &Path, options: &Options) -> Result<Voxels, String> {
+    // wfc setup
+    let x_size = 8;
+    let y_size = 8;
+    let z_size = 4;
+    let model = set.model();
+    let weights = load_weight_maps(&set, options)?;
+    let params = Params::new(set.fields(), &model, options.borders, options.periodic)
+        .with_weights(weights.as_ref())
+        .with_limits(set.limits())
+        .with_connections(set.connections())
+        .with_heuristic(options.heuristic);
+    let mut wave = Wave::volume(&params, x_size, y_size, z_size)?;
+    apply_constraints(&set, &params, &mut wave, options)?;
+
+    if let Err(contradiction) = solve(&params, &mut wave, options.seed, &options.backtracking) {
+        print_wave(set.fields(), wave.cells());
+        return Err(contradiction.to_string());
+    }
+
+    let path = json
+        .parent()
+        .expect("json should be in a directroy")
+        .join(set.dir());
+    let mut models = HashMap::with_capacity(set.fields().len());
+    for field in set.fields() {
+        models.insert(
+            field.img_name().clone(),
+            Voxels::load(&path.join(field.img_name()))?,
+        );
+    }
+    assemble(set.fields(), &models, wave.cells(), z_size)
+}
+
+pub fn interactive_render(set: Set, json: &Path, options: &Options) -> Result<(), String> {
     // sdl2 setup
     let img_size: u32 = 14;
     let sdl_context = sdl2::init()?;
@@ -121,15 +460,16 @@ pub fn interactive_render(set: Set, json: &Path) -> Result<(), String> {
     // wfc setup
     let x_size = 4;
     let y_size = 4;
-    let base_vec = set.fields().iter().collect_vec();
-    let mut side = Vec::with_capacity(32);
-    for _ in 0..side.capacity() {
-        side.push(base_vec.clone());
-    }
-    let sides = [side.clone(), side.clone(), side.clone(), side.clone()];
-    let params = Params::new(&set.fields(), &sides);
-    let mut wave: Array2D<Vec<&Field>> =
-        Array2D::filled_with(set.fields().iter().collect_vec(), y_size, x_size);
+    let model = set.model();
+    let weights = load_weight_maps(&set, options)?;
+    let params = Params::new(set.fields(), &model, options.borders, options.periodic)
+        .with_weights(weights.as_ref())
+        .with_limits(set.limits())
+        .with_connections(set.connections())
+        .with_heuristic(options.heuristic);
+    let mut wave = Wave::filled(&params, x_size, y_size)?;
+    apply_constraints(&set, &params, &mut wave, options)?;
+    let mut generator = Generator::new(&params, wave, options.seed, options.backtracking);
 
     'mainloop: loop {
         for event in sdl_context.event_pump()?.poll_iter() {
@@ -144,16 +484,13 @@ pub fn interactive_render(set: Set, json: &Path) -> Result<(), String> {
         }
 
         // Display
-        for x in 0..wave.row_len() {
-            for y in 0..wave.column_len() {
-                let fields: &Vec<&Field> = wave.get(y, x).expect("coord should be in wave");
-                let target = Rect::new(
-                    (x as u32 * img_size) as i32,
-                    (y as u32 * img_size) as i32,
-                    img_size,
-                    img_size,
-                );
-                for field in fields {
+        let wave = generator.wave();
+        canvas.clear();
+        for x in 0..wave.cells().row_len() {
+            for y in 0..wave.cells().column_len() {
+                let tiles: Tiles = wave.cells().get(y, x).expect("coord should be in wave");
+                let target = tile_rect(*model.topology(), x, y, img_size);
+                for field in tiles.iter().map(|tile| &set.fields()[tile]) {
                     let texture = pngs
                         .get(field.img_name())
                         .expect("wave should only produce names in the set");
@@ -163,7 +500,7 @@ pub fn interactive_render(set: Set, json: &Path) -> Result<(), String> {
                         target,
                         *field.rotation() as f64,
                         None,
-                        false,
+                        *field.mirrored(),
                         false,
                     )?;
                 }
@@ -172,46 +509,104 @@ pub fn interactive_render(set: Set, json: &Path) -> Result<(), String> {
         canvas.present();
 
         // input
-        println!("Which field do you want to view? (x,y)");
+        println!(
+            "Which field do you want to view? (x,y, nothing to let the generator step, u to undo or r to redo)"
+        );
         let mut input = String::new();
         io::stdin()
             .read_line(&mut input)
             .expect("Failed to read input");
-        input.pop();
-        input.pop();
-        let mut split = input.split(",");
-        let x: usize = split
-            .next()
-            .expect("invalid input")
-            .parse()
-            .expect("Not a number");
-        let y: usize = split
-            .next()
-            .expect("invalid input")
-            .parse()
-            .expect("Not a number");
+        match input.trim() {
+            "" => {
+                match generator.step() {
+                    Ok(Some(step)) => print_step(set.fields(), &step),
+                    Ok(None) => println!("the wave is collapsed"),
+                    Err(contradiction) => println!("{}", contradiction),
+                }
+                continue;
+            }
+            "u" => {
+                match generator.undo() {
+                    Some(step) => {
+                        print!("took back: ");
+                        print_step(set.fields(), &step);
+                    }
+                    None => println!("nothing to undo"),
+                }
+                continue;
+            }
+            "r" => {
+                match generator.redo() {
+                    Some(step) => print_step(set.fields(), &step),
+                    None => println!("nothing to redo"),
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let (x, y) = match parse_coord(input.trim()) {
+            Some((x, y)) if wave.cells().get(y, x).is_some() => (x, y),
+            _ => {
+                println!(
+                    "{} is not a field of the {}x{} wave",
+                    input.trim(),
+                    wave.cells().row_len(),
+                    wave.cells().column_len()
+                );
+                continue;
+            }
+        };
+        let entry = wave.cells().get(y, x).unwrap();
         println!(
             "field {}, {} is: {}",
             x,
             y,
-            entry_string(wave.get(y, x).expect("Coord has to be in wave"))
+            entry_string(set.fields(), entry)
         );
         println!("To which entry do you want to collapse it? (number)");
         input.clear();
         io::stdin()
             .read_line(&mut input)
             .expect("Failed to read input");
-        input.pop();
-        input.pop();
-        let n: usize = input.parse().expect("Not a number");
-        wave.set(
-            y,
-            x,
-            vec![wave.get(y, x).unwrap().get(n).expect("Outside field len")],
-        )
-        .expect("Outside wave");
-        update_field(&params, &mut wave, Coord::new(x, y));
+        let tile = match input
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| entry.iter().nth(n))
+        {
+            Some(tile) => tile,
+            None => {
+                println!("{} is not one of the {} entries", input.trim(), entry.len());
+                continue;
+            }
+        };
+        let step = generator.choose(Coord::new(x, y), tile);
+        print_step(set.fields(), &step);
     }
 
     Ok(())
 }
+
+/// The `x,y` coordinates of a field as typed by the user.
+fn parse_coord(input: &str) -> Option<(usize, usize)> {
+    let (x, y) = input.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+fn print_step(fields: &[Field], step: &Step) {
+    println!(
+        "{} field {}, {} as {}, {} fields changed",
+        if *step.backtracked() {
+            "banned"
+        } else {
+            "chose"
+        },
+        step.pos().x(),
+        step.pos().y(),
+        fields[*step.tile()].img_name(),
+        step.changed().len()
+    );
+    if let Some(contradiction) = step.contradiction() {
+        println!("{}, stepping back next", contradiction);
+    }
+}
@@ -0,0 +1,36 @@
+//! Fields and borders shared by the unit tests of several modules.
+
+use crate::boundary::Border;
+use crate::collapse::Field;
+
+/// A square field of weight 1 with `sides` clockwise from the top.
+pub fn field(name: &str, sides: [&str; 4]) -> Field {
+    Field::new(
+        name.to_string(),
+        0,
+        sides.map(|side| side.to_string()).to_vec(),
+        1,
+    )
+}
+
+/// Two fields that stack in columns and alternate in rows.
+pub fn stripes() -> Vec<Field> {
+    vec![
+        field("a", ["i-A", "i-B", "i-A", "i-C"]),
+        field("b", ["i-A", "i-C", "i-A", "i-B"]),
+    ]
+}
+
+/// Three colors that never lie next to themselves.
+pub fn colors() -> Vec<Field> {
+    vec![
+        field("a", ["i-C-u_a"; 4]),
+        field("b", ["i-C-u_b"; 4]),
+        field("c", ["i-C-u_c"; 4]),
+    ]
+}
+
+/// Any tile may lie on every edge.
+pub fn free_borders() -> [Border; 4] {
+    [(); 4].map(|_| Border::free())
+}
@@ -0,0 +1,263 @@
+use std::collections::{HashSet, VecDeque};
+
+use getset::Getters;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::collapse::{
+    ban, observe, update_field, update_wave, Backtracking, Contradiction, Coord, Diff, Params, Wave,
+};
+use crate::connection;
+use crate::limit;
+use crate::tileset::TileSet;
+
+/// One step of a collapse: the cell that was observed and the tile chosen for it, or,
+/// after a contradiction, the decision that was taken back and the tile banned instead.
+/// `changed` lists every cell whose candidates changed, each once.
+/// `contradiction` is set if propagating the step ran a cell out of candidates.
+#[derive(Clone, PartialEq, Eq, Debug, new, Getters)]
+#[getset(get = "pub")]
+pub struct Step {
+    /// The observed cell.
+    pos: Coord,
+    /// The tile chosen for the cell, or banned from it after backtracking.
+    tile: usize,
+    /// Whether the step took back a decision instead of observing a cell.
+    backtracked: bool,
+    /// Every cell whose candidates changed.
+    changed: Vec<Coord>,
+    /// Set if propagating the step ran a cell out of candidates.
+    contradiction: Option<Contradiction>,
+}
+
+/// A step that was applied to the wave, with what it removed. Backtracking also keeps
+/// the steps it took back, so it can be undone in turn.
+#[derive(Clone, Debug)]
+struct Record {
+    step: Step,
+    diff: Diff,
+    undone: Vec<Record>,
+}
+
+/// Owns a wave and collapses it one observation at a time, so front ends can show or
+/// steer every step. Iterating it yields the steps until the wave is collapsed or a
+/// contradiction cannot be resolved within `backtracking`.
+/// Every step is recorded as a diff, so steps can be undone and redone exactly, back to
+/// the oldest of the last `backtracking.depth` observations.
+#[derive(Getters)]
+pub struct Generator<'g> {
+    params: &'g Params<'g>,
+    /// The wave as of the last step.
+    #[getset(get = "pub")]
+    wave: Wave,
+    rng: SmallRng,
+    backtracking: Backtracking,
+    history: Vec<Record>,
+    future: Vec<Record>,
+    /// The positions in `history` of the observations backtracking may take back. Steps
+    /// before the first of them are forgotten.
+    decisions: VecDeque<usize>,
+    attempts: usize,
+    pending: Option<Contradiction>,
+    done: bool,
+}
+
+impl<'g> Generator<'g> {
+    /// Applies the borders, tile limits and connections to `wave` right away. Should they already
+    /// contradict each other, the first step fails.
+    pub fn new(
+        params: &'g Params<'g>,
+        mut wave: Wave,
+        seed: u64,
+        backtracking: Backtracking,
+    ) -> Generator<'g> {
+        let pending = update_wave(params, &mut wave)
+            .and_then(|_| settle(params, &mut wave, Coord::new(0, 0)))
+            .err();
+        wave.take_diff();
+        Generator {
+            params,
+            wave,
+            rng: SmallRng::seed_from_u64(seed),
+            backtracking,
+            history: Vec::new(),
+            future: Vec::new(),
+            decisions: VecDeque::with_capacity(*backtracking.depth()),
+            attempts: 0,
+            pending,
+            done: false,
+        }
+    }
+
+    /// Takes back the last decision after a contradiction, or else observes the cell the
+    /// heuristic of the params selects. Returns `None` once every cell is decided.
+    pub fn step(&mut self) -> Result<Option<Step>, Contradiction> {
+        if let Some(contradiction) = self.pending.take() {
+            return self.backtrack(contradiction).map(Some);
+        }
+        let pos = match self
+            .params
+            .heuristic()
+            .select(self.params, &self.wave, &mut self.rng)
+        {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        let tile = observe(self.params, self.wave.cells(), pos, &mut self.rng);
+        Ok(Some(self.choose(pos, tile)))
+    }
+
+    /// Decides `pos` to be `tile`, e.g. picked by hand, and propagates the choice.
+    /// A resulting contradiction is resolved by the next step.
+    pub fn choose(&mut self, pos: Coord, tile: usize) -> Step {
+        let chosen = TileSet::single(self.params.model().tile_count(), tile);
+        let result = update_field(self.params, &mut self.wave, pos, &chosen)
+            .and_then(|_| settle(self.params, &mut self.wave, pos));
+        let diff = self.wave.take_diff();
+        let step = Step::new(pos, tile, false, diff.cells(), result.err());
+        self.future.clear();
+        self.push(Record {
+            step: step.clone(),
+            diff,
+            undone: Vec::new(),
+        });
+        step
+    }
+
+    /// Takes back the last step, returning the wave to exactly the state before it.
+    /// Returns the step or `None` if there is nothing left to undo.
+    pub fn undo(&mut self) -> Option<Step> {
+        let record = self.history.pop()?;
+        self.wave.undo(self.params, &record.diff);
+        if self.decisions.back() == Some(&self.history.len()) {
+            self.decisions.pop_back();
+        }
+        if *record.step.backtracked() {
+            self.attempts -= 1;
+            for undone in &record.undone {
+                self.wave.redo(self.params, &undone.diff);
+                self.push(undone.clone());
+            }
+        }
+        self.pending = self.last_contradiction();
+        self.done = false;
+        let step = record.step.clone();
+        self.future.push(record);
+        Some(step)
+    }
+
+    /// Applies the last undone step again. Returns the step or `None` if nothing was
+    /// undone since the last new step.
+    pub fn redo(&mut self) -> Option<Step> {
+        let record = self.future.pop()?;
+        if *record.step.backtracked() {
+            self.attempts += 1;
+            for undone in self
+                .history
+                .split_off(self.history.len() - record.undone.len())
+                .iter()
+                .rev()
+            {
+                self.wave.undo(self.params, &undone.diff);
+            }
+            self.decisions.pop_back();
+        }
+        self.wave.redo(self.params, &record.diff);
+        let step = record.step.clone();
+        self.push(record);
+        self.done = false;
+        Some(step)
+    }
+
+    /// The wave as of the last step, ending the generation.
+    pub fn into_wave(self) -> Wave {
+        self.wave
+    }
+
+    /// Undoes every step back to the last remembered observation and bans its tile.
+    fn backtrack(&mut self, contradiction: Contradiction) -> Result<Step, Contradiction> {
+        let decision = match self.decisions.pop_back() {
+            Some(decision) if self.attempts < *self.backtracking.attempts() => decision,
+            _ => return Err(contradiction),
+        };
+        self.attempts += 1;
+        let undone = self.history.split_off(decision);
+        for record in undone.iter().rev() {
+            self.wave.undo(self.params, &record.diff);
+        }
+        let (pos, tile) = (*undone[0].step.pos(), *undone[0].step.tile());
+        let result = ban(self.params, &mut self.wave, pos, tile)
+            .and_then(|()| settle(self.params, &mut self.wave, pos));
+        let diff = self.wave.take_diff();
+        let mut changed = undone
+            .iter()
+            .flat_map(|record| record.diff.cells())
+            .collect::<Vec<_>>();
+        changed.extend(diff.cells());
+        let mut seen = HashSet::new();
+        changed.retain(|&pos| seen.insert(pos));
+        let step = Step::new(pos, tile, true, changed, result.err());
+        self.future.clear();
+        self.push(Record {
+            step: step.clone(),
+            diff,
+            undone,
+        });
+        Ok(step)
+    }
+
+    /// Appends a step that was applied to the wave, remembering it as a decision if it
+    /// was an observation. Once more than `depth` decisions are remembered, the oldest
+    /// one and the steps up to the next are dropped, so the history stays bounded.
+    fn push(&mut self, record: Record) {
+        let depth = *self.backtracking.depth();
+        if !record.step.backtracked() {
+            if self.decisions.len() == depth {
+                self.decisions.pop_front();
+                let kept = self
+                    .decisions
+                    .front()
+                    .copied()
+                    .unwrap_or(self.history.len());
+                self.history.drain(..kept);
+                self.decisions
+                    .iter_mut()
+                    .for_each(|decision| *decision -= kept);
+            }
+            if depth > 0 {
+                self.decisions.push_back(self.history.len());
+            }
+        }
+        self.pending = record.step.contradiction().clone();
+        self.history.push(record);
+    }
+
+    fn last_contradiction(&self) -> Option<Contradiction> {
+        self.history
+            .last()
+            .and_then(|record| record.step.contradiction().clone())
+    }
+}
+
+/// Enforces the tile limits and connections of `params` after a change at `pos`, again
+/// and again while one of them still restricts the wave, as each may affect the other.
+fn settle(params: &Params, wave: &mut Wave, pos: Coord) -> Result<(), Contradiction> {
+    while limit::enforce(params, wave, pos)? | connection::enforce(params, wave, pos)? {}
+    Ok(())
+}
+
+impl Iterator for Generator<'_> {
+    type Item = Result<Step, Contradiction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let step = self.step().transpose();
+        self.done = !matches!(step, Some(Ok(_)));
+        step
+    }
+}
+
+#[cfg(test)]
+mod generator_test;
@@ -0,0 +1,132 @@
+use super::*;
+use crate::collapse::solve;
+use crate::fixtures::{colors, field, free_borders, stripes};
+use crate::model::Model;
+
+#[test]
+fn steps_until_collapsed_like_solve() {
+    let fields = colors();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    for seed in 0..10 {
+        let wave = Wave::filled(&params, 6, 6).unwrap();
+        let mut solved = wave.clone();
+        solve(&params, &mut solved, seed, &Backtracking::default()).unwrap();
+
+        let mut generator = Generator::new(&params, wave, seed, Backtracking::default());
+        let steps = generator.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(steps.iter().all(|step| !step.changed().is_empty()));
+        assert!(generator.next().is_none());
+        assert_eq!(generator.into_wave(), solved);
+    }
+}
+
+#[test]
+fn step_reports_observation_and_changed_cells() {
+    let fields = stripes();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let wave = Wave::filled(&params, 3, 1).unwrap();
+    let mut generator = Generator::new(&params, wave, 0, Backtracking::default());
+
+    let step = generator.choose(Coord::new(1, 0), 1);
+    assert!(!step.backtracked());
+    assert!(step.contradiction().is_none());
+    assert_eq!(
+        step.changed(),
+        &vec![Coord::new(1, 0), Coord::new(2, 0), Coord::new(0, 0)]
+    );
+    assert_eq!(generator.step(), Ok(None));
+}
+
+#[test]
+fn contradiction_is_taken_back_by_the_next_step() {
+    // the tile only fits next to itself vertically, so a row of two never fits
+    let fields = vec![
+        field("a", ["i-A", "i-X", "i-A", "i-Y"]),
+        field("b", ["i-B"; 4]),
+    ];
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let wave = Wave::filled(&params, 2, 1).unwrap();
+    let mut generator = Generator::new(&params, wave, 0, Backtracking::default());
+
+    let step = generator.choose(Coord::new(0, 0), 0);
+    assert!(step.contradiction().is_some());
+    let step = generator.step().unwrap().unwrap();
+    assert!(step.backtracked());
+    assert_eq!((*step.pos(), *step.tile()), (Coord::new(0, 0), 0));
+    assert!(step.contradiction().is_none());
+    assert!(generator
+        .wave()
+        .cells()
+        .elements_row_major_iter()
+        .all(|tiles| tiles.iter().collect::<Vec<_>>() == vec![1]));
+}
+
+#[test]
+fn undo_and_redo_restore_the_wave_exactly() {
+    let fields = colors();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    for seed in 0..10 {
+        let wave = Wave::filled(&params, 6, 6).unwrap();
+        let mut generator = Generator::new(&params, wave.clone(), seed, Backtracking::default());
+        let mut states = vec![wave];
+        let mut steps = Vec::new();
+        while let Some(step) = generator.step().unwrap() {
+            steps.push(step);
+            states.push(generator.wave().clone());
+        }
+
+        // backtracking steps are undone as well, bringing back what they took back
+        for (state, step) in states.iter().rev().skip(1).zip(steps.iter().rev()) {
+            assert_eq!(generator.undo().as_ref(), Some(step));
+            assert_eq!(generator.wave(), state);
+        }
+        assert_eq!(generator.undo(), None);
+        for (state, step) in states.iter().skip(1).zip(&steps) {
+            assert_eq!(generator.redo().as_ref(), Some(step));
+            assert_eq!(generator.wave(), state);
+        }
+        assert_eq!(generator.redo(), None);
+        assert_eq!(generator.step(), Ok(None));
+    }
+}
+
+#[test]
+fn a_new_step_discards_the_redo_history() {
+    let fields = stripes();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let wave = Wave::filled(&params, 3, 1).unwrap();
+    let mut generator = Generator::new(&params, wave.clone(), 0, Backtracking::default());
+
+    generator.choose(Coord::new(0, 0), 0);
+    generator.undo();
+    assert_eq!(generator.wave(), &wave);
+    generator.choose(Coord::new(0, 0), 1);
+    assert_eq!(generator.redo(), None);
+}
+
+#[test]
+fn history_reaches_back_depth_decisions() {
+    let fields = vec![field("a", ["i-A"; 4]), field("b", ["i-A"; 4])];
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let wave = Wave::filled(&params, 4, 1).unwrap();
+    let mut generator = Generator::new(&params, wave, 0, Backtracking::new(2, 256));
+
+    for x in 0..4 {
+        generator.choose(Coord::new(x, 0), 0);
+    }
+    assert_eq!(*generator.undo().unwrap().pos(), Coord::new(3, 0));
+    assert_eq!(*generator.undo().unwrap().pos(), Coord::new(2, 0));
+    assert_eq!(generator.undo(), None);
+}
@@ -0,0 +1,77 @@
+use rand::seq::IteratorRandom;
+use rand::{Rng, RngCore};
+
+use crate::collapse::{lowest_entropy, Coord, Params, Wave};
+use crate::tileset::{Cells, Tiles};
+
+/// Chooses which undecided cell the [`Generator`](crate::generator::Generator) observes
+/// next. Shared by all threads of a batch, so it has to be `Sync`.
+pub trait Heuristic: Sync {
+    /// One of the cells of `wave` with more than one candidate, or `None` once every
+    /// cell is decided.
+    fn select(&self, params: &Params, wave: &Wave, rng: &mut dyn RngCore) -> Option<Coord>;
+}
+
+/// The cell with the lowest Shannon entropy of its candidates' weights, the default.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Entropy;
+
+/// The cell with the fewest candidates left, ignoring their weights.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RemainingValues;
+
+/// The first undecided cell row by row, so the grid fills from the top left.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Scanline;
+
+/// Any undecided cell, each equally likely.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Random;
+
+impl Heuristic for Entropy {
+    fn select(&self, _params: &Params, wave: &Wave, rng: &mut dyn RngCore) -> Option<Coord> {
+        lowest_entropy(wave, rng)
+    }
+}
+
+impl Heuristic for RemainingValues {
+    fn select(&self, _params: &Params, wave: &Wave, rng: &mut dyn RngCore) -> Option<Coord> {
+        undecided(wave.cells())
+            .map(|(pos, entry)| (pos, entry.len() as f64 + rng.gen::<f64>() * 1e-6))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(pos, _)| pos)
+    }
+}
+
+impl Heuristic for Scanline {
+    fn select(&self, _params: &Params, wave: &Wave, _rng: &mut dyn RngCore) -> Option<Coord> {
+        undecided(wave.cells()).map(|(pos, _)| pos).next()
+    }
+}
+
+impl Heuristic for Random {
+    fn select(&self, _params: &Params, wave: &Wave, rng: &mut dyn RngCore) -> Option<Coord> {
+        undecided(wave.cells()).map(|(pos, _)| pos).choose(rng)
+    }
+}
+
+/// The built-in heuristic called `name`: `entropy`, `mrv`, `scanline` or `random`.
+pub fn named(name: &str) -> Result<&'static dyn Heuristic, String> {
+    match name {
+        "entropy" => Ok(&Entropy),
+        "mrv" => Ok(&RemainingValues),
+        "scanline" => Ok(&Scanline),
+        "random" => Ok(&Random),
+        _ => Err(format!("unknown heuristic {}", name)),
+    }
+}
+
+fn undecided(cells: &Cells) -> impl Iterator<Item = (Coord, Tiles<'_>)> {
+    cells
+        .enumerate_row_major()
+        .filter(|(_, entry)| entry.len() > 1)
+        .map(|((y, x), entry)| (Coord::new(x, y), entry))
+}
+
+#[cfg(test)]
+mod heuristic_test;
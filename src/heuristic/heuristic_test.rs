@@ -0,0 +1,112 @@
+use array2d::Array2D;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use super::*;
+use crate::collapse::{solve, Backtracking, Wave};
+use crate::fixtures::{colors, free_borders};
+use crate::generator::Generator;
+use crate::model::Model;
+use crate::tileset::TileSet;
+
+/// Collapses the grid from the bottom right, to check that custom heuristics are used.
+struct Backwards;
+
+impl Heuristic for Backwards {
+    fn select(&self, _params: &Params, wave: &Wave, _rng: &mut dyn RngCore) -> Option<Coord> {
+        undecided(wave.cells()).map(|(pos, _)| pos).last()
+    }
+}
+
+#[test]
+fn scanline_selects_first_undecided_cell() {
+    let fields = colors();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let mut cells = Array2D::filled_with(TileSet::full(3), 2, 3);
+    cells[(0, 0)] = TileSet::single(3, 0);
+    cells[(0, 1)] = TileSet::single(3, 1);
+    let wave = Wave::new(&params, Cells::from(&cells)).unwrap();
+    let mut rng = SmallRng::seed_from_u64(0);
+    assert_eq!(
+        Scanline.select(&params, &wave, &mut rng),
+        Some(Coord::new(2, 0))
+    );
+}
+
+#[test]
+fn remaining_values_selects_fewest_candidates() {
+    let fields = colors();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let mut cells = Array2D::filled_with(TileSet::full(3), 3, 3);
+    cells[(1, 0)] = TileSet::single(3, 2);
+    let mut pair = TileSet::full(3);
+    pair.remove(0);
+    cells[(2, 1)] = pair;
+    let wave = Wave::new(&params, Cells::from(&cells)).unwrap();
+    for seed in 0..10 {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        assert_eq!(
+            RemainingValues.select(&params, &wave, &mut rng),
+            Some(Coord::new(1, 2))
+        );
+    }
+}
+
+#[test]
+fn random_selects_only_undecided_cells() {
+    let fields = colors();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false);
+    let mut cells = Array2D::filled_with(TileSet::single(3, 0), 3, 3);
+    cells[(0, 2)] = TileSet::full(3);
+    cells[(2, 1)] = TileSet::full(3);
+    let wave = Wave::new(&params, Cells::from(&cells)).unwrap();
+    let mut rng = SmallRng::seed_from_u64(0);
+    let selected = (0..50)
+        .map(|_| Random.select(&params, &wave, &mut rng).unwrap())
+        .collect::<Vec<_>>();
+    assert!(selected.contains(&Coord::new(2, 0)));
+    assert!(selected.contains(&Coord::new(1, 2)));
+    assert!(selected
+        .iter()
+        .all(|&pos| pos == Coord::new(2, 0) || pos == Coord::new(1, 2)));
+
+    let decided = Cells::filled_with(&TileSet::single(3, 0), 3, 3);
+    let decided = Wave::new(&params, decided).unwrap();
+    assert_eq!(Random.select(&params, &decided, &mut rng), None);
+}
+
+#[test]
+fn every_heuristic_solves() {
+    let fields = colors();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    for name in ["entropy", "mrv", "scanline", "random"] {
+        let params =
+            Params::new(&fields, &model, &borders, false).with_heuristic(named(name).unwrap());
+        let mut wave = Wave::filled(&params, 5, 4).unwrap();
+        solve(&params, &mut wave, 3, &Backtracking::default()).unwrap();
+        assert!(wave
+            .cells()
+            .elements_row_major_iter()
+            .all(|entry| entry.len() == 1));
+    }
+    assert!(named("spiral").is_err());
+}
+
+#[test]
+fn generator_uses_custom_heuristic() {
+    let fields = colors();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let params = Params::new(&fields, &model, &borders, false).with_heuristic(&Backwards);
+    let wave = Wave::filled(&params, 3, 2).unwrap();
+    let mut generator = Generator::new(&params, wave, 0, Backtracking::default());
+    let step = generator.step().unwrap().unwrap();
+    assert_eq!(*step.pos(), Coord::new(2, 1));
+}
@@ -0,0 +1,71 @@
+//! Wave function collapse on square, hex and voxel grids.
+//!
+//! Load a set with [`parser::load`], build the [`Model`] of which fields fit next to each
+//! other with [`Set::model`] and collapse a [`Wave`] with [`solve`], or step by step with a [`Generator`].
+//! Solved waves can be printed with [`print_wave`], saved as example maps with
+//! [`parser::save_map`] or assembled into [`voxel::Voxels`].
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use wave_function_collapse::boundary::Border;
+//! use wave_function_collapse::{parser, print_wave, solve, Backtracking, Params, Wave};
+//!
+//! let set = parser::load(Path::new("res/circuit.json"))?;
+//! let model = set.model();
+//! let borders = [(); 4].map(|_| Border::free());
+//! let params = Params::new(set.fields(), &model, &borders, false);
+//! let mut wave = Wave::filled(&params, 16, 16)?;
+//! solve(&params, &mut wave, 0, &Backtracking::default()).map_err(|e| e.to_string())?;
+//! print_wave(set.fields(), wave.cells());
+//! # Ok::<(), String>(())
+//! ```
+
+#![warn(missing_docs)]
+
+#[macro_use]
+extern crate derive_new;
+
+/// Solving many seeds of the same wave in parallel.
+pub mod batch;
+/// Borders that fix the sides along the edges of a grid.
+pub mod boundary;
+/// Waves, their propagation and the solver.
+pub mod collapse;
+/// Side labels whose cells have to form one connected network.
+pub mod connection;
+/// Collapsing a wave one step at a time, with backtracking, undo and redo.
+pub mod generator;
+/// Strategies for choosing the next cell to observe.
+pub mod heuristic;
+/// Minimum and maximum counts of tiles.
+pub mod limit;
+/// Which fields fit next to each other in every direction.
+pub mod model;
+/// Learning fields from the patterns of a sample image.
+pub mod overlapping;
+/// Loading sets, constraint maps and weight files, and saving example maps.
+pub mod parser;
+/// Sets of candidate tiles.
+pub mod tileset;
+/// Square, hex and voxel grids.
+pub mod topology;
+/// MagicaVoxel models and assembling solved volumes.
+pub mod voxel;
+/// Position dependent tile weights.
+pub mod weights;
+/// Infinite worlds generated chunk by chunk.
+pub mod world;
+
+#[cfg(test)]
+mod fixtures;
+
+pub use collapse::{
+    print_wave, solve, Backtracking, Contradiction, Coord, Field, Params, Reason, Wave,
+};
+pub use generator::{Generator, Step};
+pub use heuristic::Heuristic;
+pub use model::Model;
+pub use parser::Set;
+pub use tileset::TileSet;
+pub use topology::Topology;
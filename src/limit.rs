@@ -0,0 +1,102 @@
+use getset::Getters;
+
+use crate::collapse::{update_field, Contradiction, Coord, Params, Reason, Wave};
+use crate::tileset::TileSet;
+
+/// How many cells of a wave may end up as one of `tiles`, at least `min` and at most
+/// `max`, e.g. all rotations of a tile or every tile with the same tag.
+#[derive(Clone, PartialEq, Eq, Debug, new, Getters)]
+#[getset(get = "pub")]
+pub struct Limit {
+    /// The tiles that are counted.
+    tiles: TileSet,
+    /// How many cells have to become one of them at least.
+    min: usize,
+    /// How many cells may become one of them at most.
+    max: usize,
+}
+
+/// Applies the limits of `params` to `wave` after a change at `pos`: once a limit is
+/// reached its tiles are banned from every other cell, and once only as many cells as
+/// its minimum can still become one of its tiles they are restricted to them. Fails at
+/// `pos` if a limit cannot be kept anymore. Returns whether any cell had to be restricted.
+pub(crate) fn enforce(params: &Params, wave: &mut Wave, pos: Coord) -> Result<bool, Contradiction> {
+    let mut restricted = false;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for limit in params.limits().iter() {
+            changed |= enforce_limit(params, wave, pos, limit)?;
+        }
+        restricted |= changed;
+    }
+    Ok(restricted)
+}
+
+/// Returns whether any cell had to be restricted.
+fn enforce_limit(
+    params: &Params,
+    wave: &mut Wave,
+    pos: Coord,
+    limit: &Limit,
+) -> Result<bool, Contradiction> {
+    let mut decided = Vec::new();
+    let mut possible = Vec::new();
+    for ((y, x), tiles) in wave.cells().enumerate_row_major() {
+        let mut inside = tiles.to_set();
+        inside.intersect_with(&limit.tiles);
+        if inside.is_empty() {
+            continue;
+        }
+        if inside.len() == tiles.len() {
+            decided.push(Coord::new(x, y));
+        } else {
+            possible.push((Coord::new(x, y), inside));
+        }
+    }
+    let reachable = decided.len() + possible.len();
+    if decided.len() > limit.max {
+        return Err(
+            Contradiction::new(pos, Vec::new(), Vec::new()).with_reason(Reason::TooMany {
+                fields: names(params, limit),
+                count: decided.len(),
+                max: limit.max,
+            }),
+        );
+    }
+    if reachable < limit.min {
+        return Err(
+            Contradiction::new(pos, Vec::new(), Vec::new()).with_reason(Reason::TooFew {
+                fields: names(params, limit),
+                count: reachable,
+                min: limit.min,
+            }),
+        );
+    }
+    if decided.len() == limit.max && !possible.is_empty() {
+        for (cell, inside) in possible {
+            let mut outside = wave.cells().get(*cell.y(), *cell.x()).unwrap().to_set();
+            inside.iter().for_each(|tile| outside.remove(tile));
+            update_field(params, wave, cell, &outside)?;
+        }
+        return Ok(true);
+    }
+    if reachable == limit.min && !possible.is_empty() {
+        for (cell, inside) in possible {
+            update_field(params, wave, cell, &inside)?;
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+fn names(params: &Params, limit: &Limit) -> Vec<String> {
+    limit
+        .tiles
+        .iter()
+        .map(|tile| params.fields()[tile].img_name().clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod limit_test;
@@ -0,0 +1,97 @@
+use array2d::Array2D;
+
+use super::*;
+use crate::collapse::{solve, Backtracking, Field};
+use crate::fixtures::free_borders;
+use crate::model::Model;
+use crate::tileset::Cells;
+
+/// Two tiles that fit anywhere, the first one far more likely.
+fn fields() -> Vec<Field> {
+    ["a", "b"]
+        .iter()
+        .zip([20, 1])
+        .map(|(name, weight)| Field::new(name.to_string(), 0, vec!["i-A".to_string(); 4], weight))
+        .collect()
+}
+
+fn count(wave: &Wave, tile: usize) -> usize {
+    wave.cells()
+        .elements_row_major_iter()
+        .filter(|tiles| tiles.iter().eq([tile]))
+        .count()
+}
+
+#[test]
+fn solve_keeps_counts_within_limits() {
+    let fields = fields();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let limits = [
+        Limit::new(TileSet::single(2, 0), 0, 3),
+        Limit::new(TileSet::single(2, 1), 23, usize::MAX),
+    ];
+    let params = Params::new(&fields, &model, &borders, false).with_limits(&limits);
+    for seed in 0..10 {
+        let mut wave = Wave::filled(&params, 5, 5).unwrap();
+        solve(&params, &mut wave, seed, &Backtracking::default()).unwrap();
+        assert!(count(&wave, 0) <= 3);
+        assert!(count(&wave, 1) >= 23);
+        assert_eq!(count(&wave, 0) + count(&wave, 1), 25);
+    }
+
+    let exactly = [Limit::new(TileSet::single(2, 1), 4, 4)];
+    let params = Params::new(&fields, &model, &borders, false).with_limits(&exactly);
+    for seed in 0..10 {
+        let mut wave = Wave::filled(&params, 5, 5).unwrap();
+        solve(&params, &mut wave, seed, &Backtracking::default()).unwrap();
+        assert_eq!(count(&wave, 1), 4);
+    }
+}
+
+#[test]
+fn unreachable_limits_contradict() {
+    let fields = fields();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let limits = [Limit::new(TileSet::single(2, 1), 5, 5)];
+    let params = Params::new(&fields, &model, &borders, false).with_limits(&limits);
+    let mut wave = Wave::filled(&params, 2, 2).unwrap();
+
+    assert!(solve(&params, &mut wave, 0, &Backtracking::default()).is_err());
+}
+
+#[test]
+fn limit_contradictions_name_the_limit() {
+    let fields = fields();
+    let borders = free_borders();
+    let model = Model::new(&fields);
+    let limits = [Limit::new(TileSet::single(2, 1), 5, 5)];
+    let params = Params::new(&fields, &model, &borders, false).with_limits(&limits);
+    let mut wave = Wave::filled(&params, 2, 2).unwrap();
+    let contradiction = enforce(&params, &mut wave, Coord::new(0, 0)).unwrap_err();
+
+    assert_eq!(
+        contradiction.reason(),
+        &Reason::TooFew {
+            fields: vec!["b".to_string()],
+            count: 4,
+            min: 5
+        }
+    );
+    assert_eq!(
+        contradiction.to_string(),
+        "after field 0, 0 only 4 fields can become one of [b], but at least 5 are needed"
+    );
+
+    let limits = [Limit::new(TileSet::single(2, 0), 0, 1)];
+    let params = Params::new(&fields, &model, &borders, false).with_limits(&limits);
+    let cells = Array2D::filled_with(TileSet::single(2, 0), 1, 2);
+    let mut wave = Wave::new(&params, Cells::from(&cells)).unwrap();
+    let contradiction = enforce(&params, &mut wave, Coord::new(1, 0)).unwrap_err();
+
+    assert_eq!(
+        contradiction.to_string(),
+        "after field 1, 0 there are 2 of [a], but at most 1 are allowed"
+    );
+}
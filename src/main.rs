@@ -5,26 +5,146 @@ extern crate derive_new;
 
 use std::env;
 use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Instant;
 
-use display::interactive_render;
+use display::{
+    auto_render, auto_volume, batch_export, chunk_render, interactive_render, overlapping_render,
+    Options,
+};
+use wave_function_collapse::batch::Batch;
+use wave_function_collapse::boundary::Border;
+use wave_function_collapse::heuristic::{self, Entropy, Heuristic};
+use wave_function_collapse::{parser, Backtracking, Topology};
 
-mod collapse;
 mod console;
 mod display;
-mod parser;
 
-fn run(set: &Path) -> Result<(), String> {
-    let fields = parser::load(set);
-    interactive_render(fields, set)
+fn value<T: FromStr>(arg: Option<&String>, flag: &str) -> Result<T, String> {
+    arg.ok_or(format!("{} expects a value", flag))?
+        .parse()
+        .map_err(|_| format!("{} got an invalid value", flag))
 }
 
 fn main() -> Result<(), String> {
-    let args: Vec<String> = env::args().collect();
-    let path = args
-        .get(1)
-        .map(|string| string.as_str())
-        .unwrap_or("res\\circuit.json");
-    run(Path::new(path))?;
-
-    Ok(())
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let batch = args.first().is_some_and(|arg| arg == "batch");
+    if batch {
+        args.remove(0);
+    }
+    let mut path = "res\\circuit.json";
+    let mut auto = false;
+    let mut periodic = false;
+    let mut overlapping = None;
+    let mut chunk = None;
+    let mut count = 100;
+    let mut threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let mut out = None;
+    let mut constraints = None;
+    let mut weights = None;
+    let mut heuristic: &dyn Heuristic = &Entropy;
+    let mut borders = [
+        Border::free(),
+        Border::free(),
+        Border::free(),
+        Border::free(),
+    ];
+    let mut seed = rand::random();
+    let mut depth = *Backtracking::default().depth();
+    let mut attempts = *Backtracking::default().attempts();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--auto" => auto = true,
+            "--periodic" => periodic = true,
+            "--overlapping" => overlapping = Some(value(args.next(), "--overlapping")?),
+            "--chunk" => chunk = Some(value(args.next(), "--chunk")?),
+            "--count" => count = value(args.next(), "--count")?,
+            "--threads" => threads = value(args.next(), "--threads")?,
+            "--constraints" => {
+                constraints = Some(Path::new(
+                    args.next().ok_or("--constraints expects a value")?.as_str(),
+                ))
+            }
+            "--weights" => {
+                weights = Some(Path::new(
+                    args.next().ok_or("--weights expects a value")?.as_str(),
+                ))
+            }
+            "--heuristic" => {
+                heuristic = heuristic::named(args.next().ok_or("--heuristic expects a value")?)?
+            }
+            "--out" => out = Some(args.next().ok_or("--out expects a value")?.as_str()),
+            "--border" => {
+                let border: Border = value(args.next(), "--border")?;
+                borders = [border.clone(), border.clone(), border.clone(), border];
+            }
+            "--top" => borders[0] = value(args.next(), "--top")?,
+            "--right" => borders[1] = value(args.next(), "--right")?,
+            "--bottom" => borders[2] = value(args.next(), "--bottom")?,
+            "--left" => borders[3] = value(args.next(), "--left")?,
+            "--seed" => seed = value(args.next(), "--seed")?,
+            "--depth" => depth = value(args.next(), "--depth")?,
+            "--attempts" => attempts = value(args.next(), "--attempts")?,
+            _ => path = arg.as_str(),
+        }
+    }
+    let options = Options::new(
+        seed,
+        periodic,
+        &borders,
+        Backtracking::new(depth, attempts),
+        constraints,
+        weights,
+        heuristic,
+    );
+    if batch {
+        let start = Instant::now();
+        let report = batch_export(
+            parser::load(Path::new(path))?,
+            Path::new(path),
+            &Batch::new(seed, count, threads),
+            &options,
+            Path::new(out.unwrap_or("batch")),
+        )?;
+        for (seed, contradiction) in report.contradictions() {
+            println!("seed {}: {}", seed, contradiction);
+        }
+        for (seed, error) in report.errors() {
+            println!("seed {}: {}", seed, error);
+        }
+        println!("{} in {:.2?}", report, start.elapsed());
+        return Ok(());
+    }
+    if let Some(size) = chunk {
+        println!("seed: {}", seed);
+        return chunk_render(
+            parser::load(Path::new(path))?,
+            Path::new(path),
+            seed,
+            options.backtracking(),
+            size,
+        );
+    }
+    if let Some(n) = overlapping {
+        println!("seed: {}", seed);
+        return overlapping_render(
+            Path::new(path),
+            n,
+            Path::new(out.unwrap_or("output.png")),
+            &options,
+        );
+    }
+    let set = parser::load(Path::new(path))?;
+    if *set.topology() == Topology::Voxel {
+        println!("seed: {}", seed);
+        auto_volume(set, Path::new(path), &options)?.save(Path::new(out.unwrap_or("output.vox")))
+    } else if auto {
+        println!("seed: {}", seed);
+        auto_render(set, Path::new(path), &options)
+    } else {
+        println!("seed: {}", seed);
+        interactive_render(set, Path::new(path), &options)
+    }
 }
@@ -0,0 +1,176 @@
+use getset::Getters;
+use itertools::Itertools;
+
+use crate::collapse::{fits, Field};
+use crate::tileset::TileSet;
+use crate::topology::Topology;
+
+/// A set of fields compiled for propagation: side labels are interned once and the
+/// fields that may border each other are precomputed per direction, so propagation
+/// only has to look up tile indices instead of comparing side strings.
+#[derive(Clone, Debug, Getters)]
+#[getset(get = "pub")]
+pub struct Model {
+    /// The grid the fields are made for.
+    topology: Topology,
+    /// Every distinct side label once.
+    labels: Vec<String>,
+    /// By tile and direction, the index of the label of that side.
+    sides: Vec<Vec<usize>>,
+    /// By direction and tile, the tiles that may lie next to it in that direction.
+    compatible: Vec<Vec<TileSet>>,
+    /// How often tiles were seen next to each other, for models learned from examples.
+    adjacency: Option<Adjacency>,
+}
+
+/// How often every tile was seen next to every other tile in each direction, e.g. in
+/// an example map.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Adjacency {
+    /// By direction, tile and neighbor.
+    counts: Vec<Vec<Vec<u32>>>,
+}
+
+impl Adjacency {
+    /// No pairs yet among `tiles` tiles with `sides` directions.
+    pub fn new(tiles: usize, sides: usize) -> Adjacency {
+        Adjacency {
+            counts: vec![vec![vec![0; tiles]; tiles]; sides],
+        }
+    }
+
+    /// Counts `other` once more as the neighbor of `tile` in direction `dir`.
+    pub fn add(&mut self, tile: usize, dir: usize, other: usize) {
+        self.counts[dir][tile][other] += 1;
+    }
+
+    /// How often `other` was the neighbor of `tile` in direction `dir`.
+    pub fn count(&self, tile: usize, dir: usize, other: usize) -> u32 {
+        self.counts[dir][tile][other]
+    }
+
+    /// The share of the neighbors of `tile` in direction `dir` that were `other`.
+    pub fn frequency(&self, tile: usize, dir: usize, other: usize) -> f64 {
+        let total: u32 = self.counts[dir][tile].iter().sum();
+        match total {
+            0 => 0.0,
+            _ => self.count(tile, dir, other) as f64 / total as f64,
+        }
+    }
+}
+
+impl Model {
+    /// A flat model, square or hex depending on the number of sides of the fields.
+    /// Panics if they have neither 4 nor 6 sides, see [`Model::with_topology`] to pass
+    /// the topology instead.
+    pub fn new(fields: &[Field]) -> Model {
+        let topology = fields
+            .first()
+            .map(|field| Topology::from_sides(field.sides().len()))
+            .unwrap_or(Some(Topology::Square))
+            .expect("fields should have 4 or 6 sides");
+        Model::with_topology(fields, topology)
+    }
+
+    /// All fields need as many sides as the topology has.
+    pub fn with_topology(fields: &[Field], topology: Topology) -> Model {
+        let (labels, sides) = intern(fields, topology);
+        let label_fits = labels
+            .iter()
+            .map(|a| labels.iter().map(|b| fits(a, b)).collect_vec())
+            .collect_vec();
+        let compatible = (0..topology.sides())
+            .map(|dir| {
+                sides
+                    .iter()
+                    .map(|tile_sides| {
+                        let back = topology.opposite(dir);
+                        let mut compatible = TileSet::empty(sides.len());
+                        (0..sides.len())
+                            .filter(|&other| label_fits[tile_sides[dir]][sides[other][back]])
+                            .for_each(|other| compatible.insert(other));
+                        compatible
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+        Model {
+            topology,
+            labels,
+            sides,
+            compatible,
+            adjacency: None,
+        }
+    }
+
+    /// A model where exactly the observed pairs of `adjacency` fit, whatever the sides
+    /// of the fields say. The pair counts also weight the choice of a tile by its
+    /// decided neighbors, see [`Params::weight`](crate::collapse::Params::weight).
+    pub fn from_adjacency(fields: &[Field], topology: Topology, adjacency: Adjacency) -> Model {
+        let (labels, sides) = intern(fields, topology);
+        let compatible = (0..topology.sides())
+            .map(|dir| {
+                (0..fields.len())
+                    .map(|tile| {
+                        let mut compatible = TileSet::empty(fields.len());
+                        (0..fields.len())
+                            .filter(|&other| adjacency.count(tile, dir, other) > 0)
+                            .for_each(|other| compatible.insert(other));
+                        compatible
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+        Model {
+            topology,
+            labels,
+            sides,
+            compatible,
+            adjacency: Some(adjacency),
+        }
+    }
+
+    /// The number of tiles, one per field.
+    pub fn tile_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    /// The label of side `dir` of the tile with the given index.
+    pub fn label(&self, tile: usize, dir: usize) -> &str {
+        &self.labels[self.sides[tile][dir]]
+    }
+
+    /// The direction pointing back from a neighbor in direction `dir`.
+    pub fn opposite(&self, dir: usize) -> usize {
+        self.topology.opposite(dir)
+    }
+}
+
+/// The distinct side labels of `fields` and, per field, the index of each of its sides.
+fn intern(fields: &[Field], topology: Topology) -> (Vec<String>, Vec<Vec<usize>>) {
+    assert!(
+        fields
+            .iter()
+            .all(|field| field.sides().len() == topology.sides()),
+        "fields should all have the same number of sides"
+    );
+    let labels = fields
+        .iter()
+        .flat_map(|field| field.sides().iter().cloned())
+        .unique()
+        .collect_vec();
+    let sides = fields
+        .iter()
+        .map(|field| {
+            field
+                .sides()
+                .iter()
+                .map(|side| labels.iter().position(|label| label == side).unwrap())
+                .collect_vec()
+        })
+        .collect_vec();
+    (labels, sides)
+}
+
+#[cfg(test)]
+mod model_test;
@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use super::*;
+use crate::parser;
+
+#[test]
+fn labels_are_interned_once() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+
+    assert_eq!(model.tile_count(), set.fields().len());
+    assert_eq!(model.labels().iter().unique().count(), model.labels().len());
+    for (tile, field) in set.fields().iter().enumerate() {
+        for dir in 0..4 {
+            assert_eq!(model.label(tile, dir), field.sides()[dir]);
+        }
+    }
+}
+
+#[test]
+fn compatible_matches_fits() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+
+    for (tile, field) in set.fields().iter().enumerate() {
+        for (other, other_field) in set.fields().iter().enumerate() {
+            for dir in 0..4 {
+                assert_eq!(
+                    model.compatible()[dir][tile].iter().contains(&other),
+                    fits(
+                        &field.sides()[dir],
+                        &other_field.sides()[model.opposite(dir)]
+                    )
+                );
+            }
+        }
+    }
+}
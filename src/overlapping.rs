@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use array2d::Array2D;
+use getset::Getters;
+use itertools::Itertools;
+
+use crate::collapse::Field;
+use crate::tileset::Cells;
+
+/// The `n`x`n` patterns of a sample image, learned as fields: a pattern's weight is
+/// how often it occurs and the label of each side holds the pixels it shares with
+/// the neighbor in that direction, so two patterns fit exactly where they overlap.
+#[derive(Clone, Debug, Getters)]
+#[getset(get = "pub")]
+pub struct Overlapping {
+    /// The pixels of each pattern, row by row.
+    patterns: Vec<Vec<u32>>,
+    /// One field per pattern.
+    fields: Vec<Field>,
+}
+
+impl Overlapping {
+    /// Learns the patterns of `sample`, wrapping around its edges if `periodic`.
+    pub fn new(sample: &Array2D<u32>, n: usize, periodic: bool) -> Result<Overlapping, String> {
+        let (width, height) = (sample.row_len(), sample.column_len());
+        if n < 2 || n > width || n > height {
+            return Err(format!(
+                "patterns of size {} do not fit a {}x{} sample",
+                n, width, height
+            ));
+        }
+        let (x_count, y_count) = if periodic {
+            (width, height)
+        } else {
+            (width - n + 1, height - n + 1)
+        };
+
+        let mut patterns: Vec<Vec<u32>> = Vec::new();
+        let mut counts: Vec<u32> = Vec::new();
+        let mut index = HashMap::new();
+        for (y, x) in (0..y_count).cartesian_product(0..x_count) {
+            let pattern = (0..n)
+                .cartesian_product(0..n)
+                .map(|(dy, dx)| sample[((y + dy) % height, (x + dx) % width)])
+                .collect_vec();
+            match index.get(&pattern) {
+                Some(&i) => counts[i] += 1,
+                None => {
+                    index.insert(pattern.clone(), patterns.len());
+                    patterns.push(pattern);
+                    counts.push(1);
+                }
+            }
+        }
+
+        let fields = patterns
+            .iter()
+            .zip(counts)
+            .enumerate()
+            .map(|(i, (pattern, count))| {
+                Field::new(format!("pattern{}", i), 0, sides(pattern, n), count)
+            })
+            .collect();
+        Ok(Overlapping { patterns, fields })
+    }
+
+    /// One pixel per cell: the top left pixel of its pattern, or the average
+    /// over the candidates of a cell that has not collapsed yet.
+    pub fn image(&self, cells: &Cells) -> Array2D<u32> {
+        let pixels = cells
+            .elements_row_major_iter()
+            .map(|tiles| average(tiles.iter().map(|tile| self.patterns[tile][0])))
+            .collect_vec();
+        Array2D::from_row_major(&pixels, cells.column_len(), cells.row_len())
+            .expect("image has the size of the wave")
+    }
+}
+
+/// The side labels of a pattern: the rows or columns it shares with its neighbor.
+fn sides(pattern: &[u32], n: usize) -> Vec<String> {
+    let region = |axis: char, xs: Range<usize>, ys: Range<usize>| {
+        format!(
+            "i-{}{}",
+            axis,
+            ys.cartesian_product(xs)
+                .map(|(y, x)| format!("{:08x}", pattern[y * n + x]))
+                .join(".")
+        )
+    };
+    vec![
+        region('v', 0..n, 0..n - 1),
+        region('h', 1..n, 0..n),
+        region('v', 0..n, 1..n),
+        region('h', 0..n - 1, 0..n),
+    ]
+}
+
+/// The channel-wise average of RGBA colors, transparent if there are none.
+fn average(colors: impl Iterator<Item = u32>) -> u32 {
+    let mut sums = [0u64; 4];
+    let mut count = 0;
+    for color in colors {
+        for (channel, sum) in sums.iter_mut().enumerate() {
+            *sum += (color >> (24 - channel * 8) & 0xff) as u64;
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return 0;
+    }
+    sums.iter()
+        .enumerate()
+        .map(|(channel, sum)| ((sum / count) as u32) << (24 - channel * 8))
+        .fold(0, |color, channel| color | channel)
+}
+
+#[cfg(test)]
+mod overlapping_test;
@@ -0,0 +1,82 @@
+use super::*;
+use crate::collapse::{solve, Backtracking, Params, Wave};
+use crate::fixtures::free_borders;
+use crate::model::Model;
+use crate::tileset::TileSet;
+
+fn sample(rows: &[&[u32]]) -> Array2D<u32> {
+    Array2D::from_rows(&rows.iter().map(|row| row.to_vec()).collect_vec()).unwrap()
+}
+
+#[test]
+fn patterns_are_counted() {
+    let checker = sample(&[&[1, 2], &[2, 1]]);
+    let overlapping = Overlapping::new(&checker, 2, true).unwrap();
+
+    assert_eq!(overlapping.patterns().len(), 2);
+    assert!(overlapping
+        .fields()
+        .iter()
+        .all(|field| *field.weight() == 2));
+
+    let bounded = Overlapping::new(&checker, 2, false).unwrap();
+    assert_eq!(bounded.patterns(), &vec![vec![1, 2, 2, 1]]);
+    assert!(Overlapping::new(&checker, 3, true).is_err());
+    assert!(Overlapping::new(&checker, 1, true).is_err());
+}
+
+#[test]
+fn compatible_matches_overlap() {
+    let image = sample(&[&[1, 2, 3, 1], &[2, 2, 1, 3], &[3, 1, 1, 2]]);
+    let overlapping = Overlapping::new(&image, 2, true).unwrap();
+    let model = Model::new(overlapping.fields());
+    let offsets = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+    for (tile, a) in overlapping.patterns().iter().enumerate() {
+        for (other, b) in overlapping.patterns().iter().enumerate() {
+            for (dir, (dx, dy)) in offsets.iter().enumerate() {
+                let overlaps = (0..2i32).cartesian_product(0..2i32).all(|(y, x)| {
+                    let (bx, by) = (x - dx, y - dy);
+                    !(0..2).contains(&bx)
+                        || !(0..2).contains(&by)
+                        || a[(y * 2 + x) as usize] == b[(by * 2 + bx) as usize]
+                });
+                assert_eq!(model.compatible()[dir][tile].contains(other), overlaps);
+                assert_eq!(
+                    model.compatible()[model.opposite(dir)][other].contains(tile),
+                    overlaps
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn output_follows_sample() {
+    let stripes = sample(&[&[1, 2, 3], &[1, 2, 3], &[1, 2, 3]]);
+    let overlapping = Overlapping::new(&stripes, 2, true).unwrap();
+    let model = Model::new(overlapping.fields());
+    let borders = free_borders();
+    let params = Params::new(overlapping.fields(), &model, &borders, false);
+    let mut wave = Wave::filled(&params, 7, 5).unwrap();
+
+    solve(&params, &mut wave, 0, &Backtracking::default()).unwrap();
+    let image = overlapping.image(wave.cells());
+    for row in image.rows_iter() {
+        for (a, b) in row.tuple_windows() {
+            assert_eq!(*b, a % 3 + 1);
+        }
+    }
+    for mut column in image.columns_iter() {
+        assert!(column.all_equal());
+    }
+}
+
+#[test]
+fn undecided_cells_average() {
+    let checker = sample(&[&[0xff0000ff, 0x0000ffff], &[0x0000ffff, 0xff0000ff]]);
+    let overlapping = Overlapping::new(&checker, 2, true).unwrap();
+    let cells = Cells::filled_with(&TileSet::full(2), 1, 1);
+
+    assert_eq!(overlapping.image(&cells)[(0, 0)], 0x7f007fff);
+}
@@ -1,73 +1,542 @@
+use array2d::Array2D;
 use getset::Getters;
+use itertools::Itertools;
 use std::{fs, path::Path};
 
-use crate::collapse::Field;
-use serde::Deserialize;
+use crate::collapse::{Coord, Field};
+use crate::connection::Connection;
+use crate::limit::Limit;
+use crate::model::{Adjacency, Model};
+use crate::tileset::{Cells, TileSet};
+use crate::topology::Topology;
+use crate::weights::{self, Weights};
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 struct Data {
     name: String,
+    #[serde(default)]
     rotateable: bool,
+    #[serde(default)]
+    symmetry: Option<Symmetry>,
     sides: Vec<String>,
     weight: u32,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Which transforms of a tile look different: a class named after the letter whose
+/// symmetry the tile shares, `X`, `I`, `\\`, `T`, `L` or `F` for no symmetry at all,
+/// or a list of transforms like `90` or `m180`, see [`parse_transform`].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Symmetry {
+    Class(String),
+    Transforms(Vec<String>),
+}
+
+/// Bounds how many cells become the fields with the given `name` or `tag`, `exactly`
+/// being short for the same `min` and `max`.
+#[derive(Deserialize)]
+struct LimitData {
+    name: Option<String>,
+    tag: Option<String>,
+    min: Option<usize>,
+    max: Option<usize>,
+    exactly: Option<usize>,
 }
 
 impl Data {
-    fn to_field(&self) -> Vec<Field> {
-        let sides = [
-            self.sides.get(0).expect("missing side").to_string(),
-            self.sides.get(1).expect("missing side").to_string(),
-            self.sides.get(2).expect("missing side").to_string(),
-            self.sides.get(3).expect("missing side").to_string(),
-        ];
-        if self.rotateable {
-            let mut sides1 = sides.clone();
-            sides1.rotate_right(1);
-            let mut sides2 = sides.clone();
-            sides2.rotate_right(2);
-            let mut sides3 = sides.clone();
-            sides3.rotate_right(3);
-            vec![
-                Field::new(self.name.clone(), 0, sides, self.weight),
-                Field::new(self.name.clone(), 90, sides1, self.weight),
-                Field::new(self.name.clone(), 180, sides2, self.weight),
-                Field::new(self.name.clone(), 270, sides3, self.weight),
-            ]
-        } else {
-            vec![Field::new(self.name.clone(), 0, sides, self.weight)]
+    /// One field per transform given by the symmetry of the tile, or else one per
+    /// rotation if it is rotateable, turning the sides one step at a time. Transforms
+    /// that end up with the same sides as an earlier one only make one field.
+    fn to_field(&self, topology: Topology) -> Result<Vec<Field>, String> {
+        if self.sides.len() != topology.sides() {
+            return Err(format!(
+                "{} needs {} sides, not {}",
+                self.name,
+                topology.sides(),
+                self.sides.len()
+            ));
         }
+        let rotations = topology.rotations();
+        let transforms = match &self.symmetry {
+            Some(Symmetry::Class(class)) => {
+                let (steps, mirrors) = match class.as_str() {
+                    "X" => (1, 1),
+                    "I" | "\\" => (rotations / 2, 1),
+                    "T" | "L" => (rotations, 1),
+                    "F" => (rotations, 2),
+                    _ => return Err(format!("{} has the unknown symmetry {}", self.name, class)),
+                };
+                (0..mirrors)
+                    .cartesian_product(0..steps)
+                    .map(|(mirror, step)| (step, mirror == 1))
+                    .collect_vec()
+            }
+            Some(Symmetry::Transforms(transforms)) => transforms
+                .iter()
+                .map(|transform| {
+                    let (rotation, mirrored) = parse_transform(transform)
+                        .filter(|(rotation, _)| rotation % topology.rotation_step() == 0)
+                        .ok_or(format!(
+                            "{} has an invalid transform {}",
+                            self.name, transform
+                        ))?;
+                    let step = rotation.rem_euclid(360) / topology.rotation_step();
+                    Ok((step as usize, mirrored))
+                })
+                .collect::<Result<Vec<_>, String>>()?
+                .into_iter()
+                .unique()
+                .collect_vec(),
+            None if self.rotateable => (0..rotations).map(|step| (step, false)).collect_vec(),
+            None => vec![(0, false)],
+        };
+        Ok(transforms
+            .into_iter()
+            .map(|(step, mirrored)| {
+                let mut sides = self.sides.clone();
+                if mirrored {
+                    topology.mirror(&mut sides);
+                }
+                topology.rotate(&mut sides, step);
+                Field::new(
+                    self.name.clone(),
+                    step as i32 * topology.rotation_step(),
+                    sides,
+                    self.weight,
+                )
+                .with_mirrored(mirrored)
+            })
+            .unique_by(|field| field.sides().clone())
+            .collect())
+    }
+}
+
+/// A clockwise rotation in degrees and whether the tile is mirrored before it is turned.
+type Transform = (i32, bool);
+
+/// A clockwise rotation in degrees, prefixed with `m` if the tile is mirrored left to
+/// right before it is turned, e.g. `m90`.
+fn parse_transform(transform: &str) -> Option<Transform> {
+    let (rotation, mirrored) = match transform.strip_prefix('m') {
+        Some(rotation) => (rotation, true),
+        None => (transform, false),
+    };
+    rotation.parse().ok().map(|rotation| (rotation, mirrored))
+}
+
+/// Splits a map entry into its name and, after an `@`, its transform.
+fn split_entry(entry: &str) -> Result<(&str, Option<Transform>), String> {
+    match entry.split_once('@') {
+        Some((name, transform)) => parse_transform(transform)
+            .map(|transform| (name, Some(transform)))
+            .ok_or(format!("{} has an invalid rotation", entry)),
+        None => Ok((entry, None)),
     }
 }
 
+/// The topology defaults to square or hex depending on the number of sides.
 #[derive(Deserialize)]
 struct DataSet {
     dir: String,
+    #[serde(default)]
+    topology: Option<Topology>,
     fields: Vec<Data>,
+    #[serde(default)]
+    limits: Vec<LimitData>,
+    #[serde(default)]
+    connected: Vec<ConnectionData>,
+}
+
+/// A side label whose cells have to form one network, reaching the `[x, y]` points.
+#[derive(Deserialize)]
+struct ConnectionData {
+    label: String,
+    #[serde(default)]
+    points: Vec<[usize; 2]>,
 }
 
-#[derive(Getters)]
+/// A hand-made example grid of `name@rotation` entries, the rotation defaults to 0 and
+/// is prefixed with `m` for mirrored tiles.
+#[derive(Deserialize, Serialize)]
+struct ExampleMap {
+    dir: String,
+    map: Vec<Vec<String>>,
+}
+
+/// A partial map of the cells to constrain before generating, see [`load_constraints`].
+#[derive(Deserialize)]
+struct ConstraintMap {
+    map: Vec<Vec<String>>,
+}
+
+/// Weight multipliers for the fields matching `name`, written like a constraint map
+/// entry: either a `grid` of values or a grayscale `mask` image scaled to `0..=scale`.
+#[derive(Deserialize)]
+struct WeightData {
+    name: String,
+    grid: Option<Vec<Vec<f64>>>,
+    mask: Option<String>,
+    scale: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct WeightFile {
+    weights: Vec<WeightData>,
+}
+
+/// A loaded set file: the directory of its images, its topology, every field with its
+/// rotated and mirrored variants, and its limits and connections.
+#[derive(Clone, Debug, Getters)]
 #[getset(get = "pub")]
 pub struct Set {
+    /// The directory of the images, relative to the set file.
     dir: String,
+    /// The grid the fields are made for.
+    topology: Topology,
+    /// Every variant of every field, indexed by tile.
     fields: Vec<Field>,
+    /// Bounds on how often fields occur.
+    limits: Vec<Limit>,
+    /// Side labels whose cells have to form one network.
+    connections: Vec<Connection>,
+    /// The observed neighbors of a set learned from an example map.
+    #[getset(skip)]
+    adjacency: Option<Adjacency>,
+}
+
+impl Set {
+    /// The model of the fields, learned from the observed neighbors if there are any
+    /// and else from the side labels.
+    pub fn model(&self) -> Model {
+        match &self.adjacency {
+            Some(adjacency) => {
+                Model::from_adjacency(&self.fields, self.topology, adjacency.clone())
+            }
+            None => Model::with_topology(&self.fields, self.topology),
+        }
+    }
 }
 
 impl DataSet {
-    fn to_set(&self) -> Set {
+    fn to_set(&self) -> Result<Set, String> {
+        if self.fields.is_empty() {
+            return Err("the set has no fields".to_string());
+        }
+        let topology = self
+            .topology
+            .or_else(|| {
+                self.fields
+                    .first()
+                    .and_then(|data| Topology::from_sides(data.sides.len()))
+            })
+            .unwrap_or(Topology::Square);
         let mut fields: Vec<Field> = Vec::new();
-        self.fields
+        let mut tags: Vec<&[String]> = Vec::new();
+        for data in &self.fields {
+            let mut variants = data.to_field(topology)?;
+            tags.extend(variants.iter().map(|_| data.tags.as_slice()));
+            fields.append(&mut variants);
+        }
+        let limits = self
+            .limits
+            .iter()
+            .map(|limit| limit.to_limit(&fields, &tags))
+            .collect::<Result<_, _>>()?;
+        let connections = self
+            .connected
             .iter()
-            .for_each(|data| fields.append(&mut data.to_field()));
-        Set {
+            .map(|data| {
+                let points = data.points.iter().map(|&[x, y]| Coord::new(x, y)).collect();
+                let connection = Connection::new(&fields, &data.label, points);
+                if connection.tiles().is_empty() {
+                    return Err(format!("no field has the connected side {}", data.label));
+                }
+                Ok(connection)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Set {
             dir: self.dir.clone(),
+            topology,
             fields,
+            limits,
+            connections,
+            adjacency: None,
+        })
+    }
+}
+
+impl LimitData {
+    /// `tags` holds the tags of every field.
+    fn to_limit(&self, fields: &[Field], tags: &[&[String]]) -> Result<Limit, String> {
+        let mut tiles = TileSet::empty(fields.len());
+        for tile in 0..fields.len() {
+            let named = self.name.as_ref() == Some(fields[tile].img_name());
+            let tagged = self
+                .tag
+                .as_ref()
+                .is_some_and(|tag| tags[tile].contains(tag));
+            if named || tagged {
+                tiles.insert(tile);
+            }
+        }
+        let subject = self
+            .name
+            .as_ref()
+            .or(self.tag.as_ref())
+            .map_or("nothing", String::as_str);
+        if tiles.is_empty() {
+            return Err(format!("limit on {} matches no field", subject));
         }
+        if self.exactly.is_some() && (self.min.is_some() || self.max.is_some()) {
+            return Err(format!(
+                "limit on {} has exactly together with min or max",
+                subject
+            ));
+        }
+        let min = self.exactly.or(self.min).unwrap_or(0);
+        let max = self.exactly.or(self.max).unwrap_or(usize::MAX);
+        if min > max {
+            return Err(format!(
+                "limit on {} has a min of {} above its max of {}",
+                subject, min, max
+            ));
+        }
+        Ok(Limit::new(tiles, min, max))
+    }
+}
+
+impl ExampleMap {
+    fn to_set(&self) -> Result<Set, String> {
+        let map = self
+            .map
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|entry| {
+                        let (name, transform) = split_entry(entry)?;
+                        let (rotation, mirrored) = transform.unwrap_or((0, false));
+                        Ok((name.to_string(), rotation, mirrored))
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (fields, adjacency) = learn(&map);
+        if fields.is_empty() {
+            return Err("the map has no entries".to_string());
+        }
+        Ok(Set {
+            dir: self.dir.clone(),
+            topology: Topology::Square,
+            fields,
+            limits: Vec::new(),
+            connections: Vec::new(),
+            adjacency: Some(adjacency),
+        })
+    }
+}
+
+/// Infers one field per distinct tile of an example map, weighted by how often it
+/// occurs, and counts which tiles were seen next to each other in every direction,
+/// see [`Model::from_adjacency`]. The sides of a field are labeled with its map entry.
+pub fn learn(map: &[Vec<(String, i32, bool)>]) -> (Vec<Field>, Adjacency) {
+    let tiles = map.iter().flatten().unique().collect_vec();
+    let index = |entry: &(String, i32, bool)| tiles.iter().position(|tile| *tile == entry).unwrap();
+    let at = |x: Option<usize>, y: Option<usize>| map.get(y?)?.get(x?);
+    let mut adjacency = Adjacency::new(tiles.len(), 4);
+    for (y, row) in map.iter().enumerate() {
+        for (x, entry) in row.iter().enumerate() {
+            let around = [
+                at(Some(x), y.checked_sub(1)),
+                at(Some(x + 1), Some(y)),
+                at(Some(x), Some(y + 1)),
+                at(x.checked_sub(1), Some(y)),
+            ];
+            for (dir, other) in around.into_iter().enumerate() {
+                if let Some(other) = other {
+                    adjacency.add(index(entry), dir, index(other));
+                }
+            }
+        }
+    }
+    let fields = tiles
+        .iter()
+        .enumerate()
+        .map(|(tile, (name, rotation, mirrored))| {
+            let sides = vec![entry_name(name, *rotation, *mirrored); 4];
+            let weight = map
+                .iter()
+                .flatten()
+                .filter(|entry| entry == &tiles[tile])
+                .count();
+            Field::new(name.clone(), *rotation, sides, weight as u32).with_mirrored(*mirrored)
+        })
+        .collect();
+    (fields, adjacency)
+}
+
+/// Writes collapsed cells as an example map of `set`.
+pub fn save_map(set: &Set, cells: &Cells, path: &Path) -> Result<(), String> {
+    let map = cells
+        .rows_iter()
+        .map(|row| {
+            row.map(|tiles| match (tiles.len(), tiles.iter().next()) {
+                (1, Some(tile)) => Ok(entry(&set.fields[tile])),
+                _ => Err("only collapsed cells can be saved as a map".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let example = ExampleMap {
+        dir: set.dir.clone(),
+        map,
+    };
+    let json = serde_json::to_string_pretty(&example).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The map entry of a field, the rotation is left out if it is 0 and not mirrored.
+fn entry(field: &Field) -> String {
+    entry_name(field.img_name(), *field.rotation(), *field.mirrored())
+}
+
+fn entry_name(name: &str, rotation: i32, mirrored: bool) -> String {
+    match (rotation, mirrored) {
+        (0, false) => name.to_string(),
+        (rotation, false) => format!("{}@{}", name, rotation),
+        (rotation, true) => format!("{}@m{}", name, rotation),
     }
 }
 
-pub fn load(set: &Path) -> Set {
-    let contents = fs::read_to_string(set).expect("Couldn't find or load the set file");
-    let set_data: DataSet =
-        serde_json::from_str(contents.as_str()).expect("Couldn't parse set file");
-    set_data.to_set()
+/// Loads a partial map whose rows constrain the top left cells of a wave. An entry is
+/// empty or `*` to leave the cell free, a `name` for every variant of that tile or a
+/// `name@rotation` or mirrored `name@mrotation` for exactly one, and several of these
+/// may be joined with `|`.
+pub fn load_constraints(set: &Set, path: &Path) -> Result<Array2D<TileSet>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let constraints: ConstraintMap =
+        serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let width = constraints.map.iter().map(Vec::len).max().unwrap_or(0);
+    let rows = constraints
+        .map
+        .iter()
+        .map(|row| {
+            (0..width)
+                .map(|x| constraint(set, row.get(x).map_or("", String::as_str)))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Array2D::from_rows(&rows).map_err(|e| format!("{:?}", e))
+}
+
+/// The tiles of `set` allowed by one entry of a constraint map.
+fn constraint(set: &Set, entry: &str) -> Result<TileSet, String> {
+    let entry = entry.trim();
+    if entry.is_empty() || entry == "*" {
+        return Ok(TileSet::full(set.fields.len()));
+    }
+    let mut tiles = TileSet::empty(set.fields.len());
+    for option in entry.split('|').map(str::trim) {
+        let (name, transform) = split_entry(option)?;
+        let before = tiles.len();
+        // a transform that gives the same sides as another variant only made that one
+        let sides = transform.and_then(|transform| {
+            let base = set.fields.iter().find(|field| field.img_name() == name)?;
+            transformed_sides(base, set.topology, transform)
+        });
+        set.fields
+            .iter()
+            .positions(|field| {
+                field.img_name() == name
+                    && transform.is_none_or(|transform| {
+                        (*field.rotation(), *field.mirrored()) == transform
+                            || Some(field.sides()) == sides.as_ref()
+                    })
+            })
+            .for_each(|tile| tiles.insert(tile));
+        if tiles.len() == before {
+            return Err(format!("{} is not a field of the set", option));
+        }
+    }
+    Ok(tiles)
 }
+
+/// The sides `field` would have with `transform` instead of its own, or `None` if the
+/// rotation is not a whole number of steps.
+fn transformed_sides(
+    field: &Field,
+    topology: Topology,
+    (rotation, mirrored): Transform,
+) -> Option<Vec<String>> {
+    let step = topology.rotation_step();
+    if rotation % step != 0 {
+        return None;
+    }
+    let rotations = topology.rotations();
+    let mut sides = field.sides().clone();
+    topology.rotate(
+        &mut sides,
+        rotations - (field.rotation() / step) as usize % rotations,
+    );
+    if *field.mirrored() != mirrored {
+        topology.mirror(&mut sides);
+    }
+    topology.rotate(&mut sides, (rotation.rem_euclid(360) / step) as usize);
+    Some(sides)
+}
+
+/// Loads weight maps for the fields of `set`. Masks are read with `load_image`,
+/// relative to the directory of the weight file.
+pub fn load_weights(
+    set: &Set,
+    path: &Path,
+    mut load_image: impl FnMut(&Path) -> Result<Array2D<u32>, String>,
+) -> Result<Weights, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file: WeightFile =
+        serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut weights = Weights::new(set.fields.len());
+    for data in file.weights {
+        let map = match (&data.grid, &data.mask) {
+            (Some(grid), None) => Array2D::from_rows(grid)
+                .ok()
+                .filter(|grid| grid.num_elements() > 0)
+                .ok_or(format!(
+                    "the grid of {} needs rows of equal length",
+                    data.name
+                ))?,
+            (None, Some(mask)) => {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                weights::mask(&load_image(&dir.join(mask))?, data.scale.unwrap_or(1.0))
+            }
+            _ => return Err(format!("{} needs either a grid or a mask", data.name)),
+        };
+        constraint(set, &data.name)?
+            .iter()
+            .for_each(|tile| weights.set(tile, map.clone()));
+    }
+    Ok(weights)
+}
+
+/// Reads a set file, either a list of fields or, if it has a `map`, an example map to
+/// learn the fields from.
+pub fn load(set: &Path) -> Result<Set, String> {
+    let contents = fs::read_to_string(set).map_err(|e| format!("{}: {}", set.display(), e))?;
+    let error = |e: serde_json::Error| format!("{}: {}", set.display(), e);
+    let value: serde_json::Value = serde_json::from_str(&contents).map_err(error)?;
+    if value.get("map").is_some() {
+        serde_json::from_str::<ExampleMap>(&contents)
+            .map_err(error)?
+            .to_set()
+    } else {
+        serde_json::from_str::<DataSet>(&contents)
+            .map_err(error)?
+            .to_set()
+    }
+    .map_err(|e| format!("{}: {}", set.display(), e))
+}
+
+#[cfg(test)]
+mod parser_test;
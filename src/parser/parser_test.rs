@@ -0,0 +1,356 @@
+use std::path::Path;
+
+use super::*;
+use crate::boundary::Border;
+use crate::collapse::{solve, update_field, Backtracking, Coord, Params, Wave};
+use crate::model::Model;
+
+fn tile(name: &str, rotation: i32) -> (String, i32, bool) {
+    (name.to_string(), rotation, false)
+}
+
+/// Loads `json` as a set file named `name` in the temp dir.
+fn load_json(name: &str, json: &str) -> Result<Set, String> {
+    let path = std::env::temp_dir().join(format!("{}.json", name));
+    fs::write(&path, json).unwrap();
+    let set = load(&path);
+    fs::remove_file(&path).unwrap();
+    set
+}
+
+#[test]
+fn learned_neighbors_fit() {
+    let map = vec![
+        vec![tile("a", 0), tile("b", 0), tile("a", 0)],
+        vec![tile("c", 90), tile("c", 90), tile("b", 0)],
+    ];
+    let (fields, adjacency) = learn(&map);
+    let model = Model::from_adjacency(&fields, Topology::Square, adjacency.clone());
+
+    assert_eq!(fields.len(), 3);
+    assert_eq!(
+        fields.iter().map(|field| *field.weight()).collect_vec(),
+        vec![2, 2, 2]
+    );
+    assert_eq!(*fields[2].rotation(), 90);
+    // a only ever has b on its right, b has a or c on its left
+    assert_eq!(model.compatible()[1][0].iter().collect_vec(), vec![1]);
+    assert_eq!(model.compatible()[3][1].iter().collect_vec(), vec![0, 2]);
+    assert_eq!(model.compatible()[1][2].iter().collect_vec(), vec![1, 2]);
+    assert_eq!(model.compatible()[2][0].iter().collect_vec(), vec![1, 2]);
+    assert!(model.compatible()[0][0].is_empty());
+    // b was seen once left of a and once left of c
+    assert_eq!(adjacency.count(1, 3, 0), 1);
+    assert_eq!(adjacency.frequency(1, 3, 2), 0.5);
+
+    let borders = [(); 4].map(|_| Border::free());
+    let params = Params::new(&fields, &model, &borders, false);
+    let mut wave = Wave::filled(&params, 2, 1).unwrap();
+    let b = TileSet::single(3, 1);
+    assert_eq!(params.weight(1, Coord::new(1, 0), wave.cells()), 2.0);
+    update_field(&params, &mut wave, Coord::new(0, 0), &TileSet::single(3, 0)).unwrap();
+    assert_eq!(wave.cells().get(0, 1).unwrap().to_set(), b);
+    assert_eq!(params.weight(1, Coord::new(1, 0), wave.cells()), 1.0);
+}
+
+#[test]
+fn example_map_generates_observed_pairs() {
+    let example = Path::new("res/circuit_example.json");
+    let set = load(example).unwrap();
+    let model = set.model();
+    let borders = [
+        Border::free(),
+        Border::free(),
+        Border::free(),
+        Border::free(),
+    ];
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let mut wave = Wave::filled(&params, 8, 8).unwrap();
+
+    assert_eq!(set.fields().len(), 7);
+    solve(&params, &mut wave, 3, &Backtracking::default()).unwrap();
+    let observed: Vec<Vec<String>> =
+        serde_json::from_str::<serde_json::Value>(&fs::read_to_string(example).unwrap()).unwrap()
+            ["map"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| serde_json::from_value(row.clone()).unwrap())
+            .collect();
+    let name = |tile: usize| entry(&set.fields()[tile]);
+    let pairs = observed
+        .iter()
+        .flat_map(|row| row.iter().cloned().tuple_windows())
+        .collect_vec();
+    for row in wave.cells().rows_iter() {
+        for (a, b) in row
+            .map(|tiles| name(tiles.iter().next().unwrap()))
+            .tuple_windows()
+        {
+            assert!(pairs.contains(&(a, b)));
+        }
+    }
+}
+
+#[test]
+fn saved_maps_load_as_examples() {
+    let set = load(Path::new("res/circuit.json")).unwrap();
+    let cells = Array2D::from_rows(&[
+        vec![
+            TileSet::single(set.fields().len(), 0),
+            TileSet::single(set.fields().len(), 2),
+        ],
+        vec![
+            TileSet::single(set.fields().len(), 0),
+            TileSet::single(set.fields().len(), 2),
+        ],
+    ])
+    .unwrap();
+    let path = std::env::temp_dir().join("saved_maps_load_as_examples.json");
+
+    save_map(&set, &Cells::from(&cells), &path).unwrap();
+    let saved = load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(saved.dir(), set.dir());
+    assert_eq!(
+        saved
+            .fields()
+            .iter()
+            .map(|field| field.img_name().as_str())
+            .collect_vec(),
+        vec!["substrate.png", "bridge.png"]
+    );
+    assert_eq!(*saved.fields()[1].rotation(), 90);
+
+    let undecided = Cells::filled_with(&TileSet::full(set.fields().len()), 1, 1);
+    assert!(save_map(&set, &undecided, &path).is_err());
+}
+
+#[test]
+fn constraint_maps_select_fields() {
+    let set = load(Path::new("res/circuit.json")).unwrap();
+    let constraints = load_constraints(&set, Path::new("res/circuit_landmark.json")).unwrap();
+    let names = |tiles: &TileSet| {
+        tiles
+            .iter()
+            .map(|tile| entry(&set.fields()[tile]))
+            .collect_vec()
+    };
+
+    assert_eq!((constraints.row_len(), constraints.column_len()), (6, 6));
+    assert_eq!(names(&constraints[(0, 0)]), vec!["substrate.png"]);
+    assert_eq!(constraints[(1, 1)], TileSet::full(set.fields().len()));
+    assert_eq!(
+        names(&constraints[(5, 2)]),
+        vec!["track.png", "track.png@90", "wire.png", "wire.png@90"]
+    );
+    assert_eq!(
+        names(&constraint(&set, "track.png@180").unwrap()),
+        vec!["track.png"]
+    );
+    assert_eq!(
+        names(&constraint(&set, "skew.png@270").unwrap()),
+        vec!["skew.png@270"]
+    );
+    assert!(constraint(&set, "skew.png@45").is_err());
+    assert!(constraint(&set, "chip.png").is_err());
+}
+
+#[test]
+fn weight_files_map_grids_and_masks() {
+    let set = load(Path::new("res/circuit.json")).unwrap();
+    let mut loaded = Vec::new();
+    let weights = load_weights(&set, Path::new("res/circuit_weights.json"), |path| {
+        loaded.push(path.to_path_buf());
+        Ok(Array2D::from_rows(&[vec![0x0000_00ff, 0xffff_ffff]]).unwrap())
+    })
+    .unwrap();
+
+    assert_eq!(loaded, vec![Path::new("res/img/masks/center.png")]);
+    let component = set
+        .fields()
+        .iter()
+        .position(|field| field.img_name() == "component.png")
+        .unwrap();
+    assert_eq!(weights.multiplier(component, Coord::new(0, 0), 8, 8), 0.0);
+    assert_eq!(weights.multiplier(component, Coord::new(7, 0), 8, 8), 4.0);
+    assert_eq!(weights.multiplier(0, Coord::new(3, 3), 8, 8), 0.5);
+    assert_eq!(weights.multiplier(1, Coord::new(3, 3), 8, 8), 1.0);
+}
+
+#[test]
+fn limits_select_fields_by_name_and_tag() {
+    let json = r#"{
+        "dir": "img",
+        "fields": [
+            {"name": "floor.png", "rotateable": false, "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": 1},
+            {"name": "door.png", "rotateable": true, "sides": ["i-B", "i-A", "i-A", "i-A"], "weight": 1, "tags": ["entrance"]},
+            {"name": "gate.png", "rotateable": false, "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": 1, "tags": ["entrance"]}
+        ],
+        "limits": [
+            {"name": "floor.png", "max": 3},
+            {"tag": "entrance", "exactly": 2}
+        ]
+    }"#;
+    let set = load_json("limits_select_fields_by_name_and_tag", json).unwrap();
+
+    assert_eq!(set.limits().len(), 2);
+    assert_eq!(set.limits()[0].tiles().iter().collect_vec(), vec![0]);
+    assert_eq!((*set.limits()[0].min(), *set.limits()[0].max()), (0, 3));
+    assert_eq!(
+        set.limits()[1].tiles().iter().collect_vec(),
+        vec![1, 2, 3, 4, 5]
+    );
+    assert_eq!((*set.limits()[1].min(), *set.limits()[1].max()), (2, 2));
+}
+
+#[test]
+fn symmetry_classes_and_transforms_make_distinct_variants() {
+    let json = r#"{
+        "dir": "img",
+        "fields": [
+            {"name": "x.png", "symmetry": "X", "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": 1},
+            {"name": "i.png", "symmetry": "I", "sides": ["i-A", "i-B", "i-A", "i-B"], "weight": 1},
+            {"name": "t.png", "symmetry": "T", "sides": ["i-A", "i-B", "i-B", "i-B"], "weight": 1},
+            {"name": "f.png", "symmetry": "F", "sides": ["i-A", "p-C", "i-B", "q-D"], "weight": 1},
+            {"name": "m.png", "symmetry": ["0", "m0", "360"], "sides": ["i-A", "p-C", "i-B", "i-B"], "weight": 1},
+            {"name": "plain.png", "symmetry": "F", "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": 1},
+            {"name": "bar.png", "symmetry": ["0", "180"], "sides": ["i-A", "i-B", "i-A", "i-B"], "weight": 1}
+        ]
+    }"#;
+    let set = load_json("symmetry_classes_and_transforms", json).unwrap();
+    let count = |name: &str| {
+        set.fields()
+            .iter()
+            .filter(|field| field.img_name() == name)
+            .count()
+    };
+
+    assert_eq!(
+        [
+            "x.png",
+            "i.png",
+            "t.png",
+            "f.png",
+            "m.png",
+            "plain.png",
+            "bar.png"
+        ]
+        .map(count),
+        [1, 2, 4, 8, 2, 1, 1]
+    );
+    let mirrored = set
+        .fields()
+        .iter()
+        .find(|field| field.img_name() == "f.png" && *field.mirrored() && *field.rotation() == 90)
+        .unwrap();
+    // mirrored to i-A, p-D, i-B, q-C, then turned a quarter
+    assert_eq!(*mirrored.sides(), vec!["q-C", "i-A", "p-D", "i-B"]);
+    assert_eq!(entry(mirrored), "f.png@m90");
+    assert_eq!(
+        constraint(&set, "f.png@m90")
+            .unwrap()
+            .iter()
+            .collect_vec()
+            .len(),
+        1
+    );
+    assert_eq!(constraint(&set, "m.png").unwrap().len(), 2);
+    assert_eq!(
+        constraint(&set, "plain.png@m90").unwrap(),
+        constraint(&set, "plain.png").unwrap()
+    );
+    assert!(constraint(&set, "m.png@90").is_err());
+}
+
+#[test]
+fn set_file_errors_name_the_field() {
+    let json = r#"{
+        "dir": "img",
+        "fields": [
+            {"name": "floor.png", "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": "1"}
+        ]
+    }"#;
+    let error = load_json("set_file_errors_name_the_field", json).unwrap_err();
+    assert!(error.contains("invalid type: string \"1\""), "{}", error);
+}
+
+#[test]
+fn invalid_sets_are_errors() {
+    let invalid = [
+        (
+            r#"{"name": "a.png", "sides": ["i-A", "i-A", "i-A"], "weight": 1}"#,
+            "a.png needs 4 sides, not 3",
+        ),
+        (
+            r#"{"name": "a.png", "symmetry": "Q", "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": 1}"#,
+            "a.png has the unknown symmetry Q",
+        ),
+        (
+            r#"{"name": "a.png", "symmetry": ["45"], "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": 1}"#,
+            "a.png has an invalid transform 45",
+        ),
+    ];
+    for (field, message) in invalid {
+        let json = format!(r#"{{"dir": "img", "fields": [{}]}}"#, field);
+        let error = load_json("invalid_sets_are_errors", &json).unwrap_err();
+        assert!(error.ends_with(message), "{}", error);
+    }
+
+    let limits = [
+        (
+            r#"{"tag": "door", "max": 1}"#,
+            "limit on door matches no field",
+        ),
+        (
+            r#"{"name": "a.png", "min": 3, "max": 2}"#,
+            "limit on a.png has a min of 3 above its max of 2",
+        ),
+        (
+            r#"{"name": "a.png", "exactly": 2, "max": 4}"#,
+            "limit on a.png has exactly together with min or max",
+        ),
+    ];
+    for (limit, message) in limits {
+        let json = format!(
+            r#"{{
+                "dir": "img",
+                "fields": [{{"name": "a.png", "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": 1}}],
+                "limits": [{}]
+            }}"#,
+            limit
+        );
+        let error = load_json("invalid_sets_are_errors", &json).unwrap_err();
+        assert!(error.ends_with(message), "{}", error);
+    }
+
+    let json = r#"{
+        "dir": "img",
+        "fields": [{"name": "a.png", "sides": ["i-A", "i-A", "i-A", "i-A"], "weight": 1}],
+        "connected": [{"label": "i-Road", "points": []}]
+    }"#;
+    let error = load_json("invalid_sets_are_errors", json).unwrap_err();
+    assert!(
+        error.ends_with("no field has the connected side i-Road"),
+        "{}",
+        error
+    );
+
+    let empty = [
+        (r#"{"dir": "img", "fields": []}"#, "the set has no fields"),
+        (r#"{"dir": "img", "map": []}"#, "the map has no entries"),
+        (r#"{"dir": "img", "map": [[]]}"#, "the map has no entries"),
+    ];
+    for (json, message) in empty {
+        let error = load_json("invalid_sets_are_errors", json).unwrap_err();
+        assert!(error.ends_with(message), "{}", error);
+    }
+
+    let json = r#"{"dir": "img", "map": [["a.png", "b.png@x"]]}"#;
+    let error = load_json("invalid_sets_are_errors", json).unwrap_err();
+    assert!(
+        error.ends_with("b.png@x has an invalid rotation"),
+        "{}",
+        error
+    );
+}
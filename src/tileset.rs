@@ -0,0 +1,290 @@
+use array2d::Array2D;
+
+/// A fixed-width bitset over the tile indices of a model, one bit per tile.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TileSet {
+    blocks: Box<[u64]>,
+}
+
+/// A borrowed tile set, e.g. the candidates of one cell of [`Cells`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Tiles<'t> {
+    blocks: &'t [u64],
+}
+
+/// The candidates of every cell of a grid in one flat buffer, each cell taking the
+/// same number of blocks, so a wave needs no allocation per cell.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cells {
+    row_len: usize,
+    column_len: usize,
+    stride: usize,
+    blocks: Vec<u64>,
+}
+
+/// The number of blocks a set of `tiles` tiles takes.
+fn stride(tiles: usize) -> usize {
+    tiles.div_ceil(64)
+}
+
+impl TileSet {
+    /// No tile out of `tiles`.
+    pub fn empty(tiles: usize) -> TileSet {
+        TileSet {
+            blocks: vec![0; stride(tiles)].into_boxed_slice(),
+        }
+    }
+
+    /// Every tile out of `tiles`.
+    pub fn full(tiles: usize) -> TileSet {
+        let mut set = TileSet::empty(tiles);
+        (0..tiles).for_each(|tile| set.insert(tile));
+        set
+    }
+
+    /// Only `tile` out of `tiles`.
+    pub fn single(tiles: usize, tile: usize) -> TileSet {
+        let mut set = TileSet::empty(tiles);
+        set.insert(tile);
+        set
+    }
+
+    /// The set borrowed as [`Tiles`].
+    pub fn as_tiles(&self) -> Tiles<'_> {
+        Tiles {
+            blocks: &self.blocks,
+        }
+    }
+
+    /// Adds `tile`.
+    pub fn insert(&mut self, tile: usize) {
+        self.blocks[tile / 64] |= 1 << (tile % 64);
+    }
+
+    /// Takes `tile` out.
+    pub fn remove(&mut self, tile: usize) {
+        self.blocks[tile / 64] &= !(1 << (tile % 64));
+    }
+
+    /// Whether `tile` is in the set.
+    pub fn contains(&self, tile: usize) -> bool {
+        self.as_tiles().contains(tile)
+    }
+
+    /// The number of tiles in the set.
+    pub fn len(&self) -> usize {
+        self.as_tiles().len()
+    }
+
+    /// Whether no tile is left.
+    pub fn is_empty(&self) -> bool {
+        self.as_tiles().is_empty()
+    }
+
+    /// Adds every tile of `other`.
+    pub fn union_with<'o>(&mut self, other: impl Into<Tiles<'o>>) {
+        self.blocks
+            .iter_mut()
+            .zip(other.into().blocks)
+            .for_each(|(block, other)| *block |= other);
+    }
+
+    /// Keeps only the tiles that are also in `other`.
+    pub fn intersect_with<'o>(&mut self, other: impl Into<Tiles<'o>>) {
+        self.blocks
+            .iter_mut()
+            .zip(other.into().blocks)
+            .for_each(|(block, other)| *block &= other);
+    }
+
+    /// The tiles in `self` that are missing in `other`.
+    pub fn difference<'o>(&self, other: impl Into<Tiles<'o>>) -> TileSet {
+        self.as_tiles().difference(other)
+    }
+
+    /// The number of tiles that are in both `self` and `other`.
+    pub fn common_len<'o>(&self, other: impl Into<Tiles<'o>>) -> usize {
+        self.as_tiles().common_len(other)
+    }
+
+    /// The tiles in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + Clone + '_ {
+        self.as_tiles().iter()
+    }
+}
+
+impl<'t> From<&'t TileSet> for Tiles<'t> {
+    fn from(set: &'t TileSet) -> Tiles<'t> {
+        set.as_tiles()
+    }
+}
+
+impl<'t> Tiles<'t> {
+    /// Whether `tile` is in the set.
+    pub fn contains(self, tile: usize) -> bool {
+        self.blocks[tile / 64] & (1 << (tile % 64)) != 0
+    }
+
+    /// The number of tiles in the set.
+    pub fn len(self) -> usize {
+        self.blocks
+            .iter()
+            .map(|block| block.count_ones() as usize)
+            .sum()
+    }
+
+    /// Whether no tile is left.
+    pub fn is_empty(self) -> bool {
+        self.blocks.iter().all(|&block| block == 0)
+    }
+
+    /// The tiles in `self` that are missing in `other`.
+    pub fn difference<'o>(self, other: impl Into<Tiles<'o>>) -> TileSet {
+        TileSet {
+            blocks: self
+                .blocks
+                .iter()
+                .zip(other.into().blocks)
+                .map(|(block, other)| block & !other)
+                .collect(),
+        }
+    }
+
+    /// The number of tiles that are in both `self` and `other`.
+    pub fn common_len<'o>(self, other: impl Into<Tiles<'o>>) -> usize {
+        self.blocks
+            .iter()
+            .zip(other.into().blocks)
+            .map(|(block, other)| (block & other).count_ones() as usize)
+            .sum()
+    }
+
+    /// The tiles in ascending order.
+    pub fn iter(self) -> impl Iterator<Item = usize> + Clone + 't {
+        self.blocks.iter().enumerate().flat_map(|(i, &block)| {
+            (0..64)
+                .filter(move |bit| block & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+
+    /// An owned copy of the set.
+    pub fn to_set(self) -> TileSet {
+        TileSet {
+            blocks: self.blocks.into(),
+        }
+    }
+}
+
+impl Cells {
+    /// `num_rows` rows of `num_columns` cells, each holding `set`.
+    pub fn filled_with(set: &TileSet, num_rows: usize, num_columns: usize) -> Cells {
+        Cells {
+            row_len: num_columns,
+            column_len: num_rows,
+            stride: set.blocks.len(),
+            blocks: set.blocks.repeat(num_rows * num_columns),
+        }
+    }
+
+    /// The number of cells in a row.
+    pub fn row_len(&self) -> usize {
+        self.row_len
+    }
+
+    /// The number of cells in a column.
+    pub fn column_len(&self) -> usize {
+        self.column_len
+    }
+
+    /// The number of cells.
+    pub fn num_elements(&self) -> usize {
+        self.row_len * self.column_len
+    }
+
+    /// The candidates of the cell in `row` and `column`, `None` outside the grid.
+    pub fn get(&self, row: usize, column: usize) -> Option<Tiles<'_>> {
+        (row < self.column_len && column < self.row_len).then(|| self.cell(row, column))
+    }
+
+    /// Every `(row, column)` index, row by row.
+    pub fn indices_row_major(&self) -> impl Iterator<Item = (usize, usize)> {
+        let row_len = self.row_len;
+        (0..self.num_elements()).map(move |i| (i / row_len, i % row_len))
+    }
+
+    /// Every cell with its `(row, column)` index, row by row.
+    pub fn enumerate_row_major(&self) -> impl Iterator<Item = ((usize, usize), Tiles<'_>)> {
+        self.indices_row_major()
+            .map(|(row, column)| ((row, column), self.cell(row, column)))
+    }
+
+    /// Every cell, row by row.
+    pub fn elements_row_major_iter(&self) -> impl Iterator<Item = Tiles<'_>> {
+        self.indices_row_major()
+            .map(|(row, column)| self.cell(row, column))
+    }
+
+    /// The cells of each row.
+    pub fn rows_iter(&self) -> impl Iterator<Item = impl Iterator<Item = Tiles<'_>>> {
+        (0..self.column_len).map(move |row| (0..self.row_len).map(move |x| self.cell(row, x)))
+    }
+
+    /// Takes the tiles of `removed` out of the cell in `row` and `column`.
+    pub(crate) fn remove(&mut self, row: usize, column: usize, removed: &TileSet) {
+        self.cell_mut(row, column)
+            .iter_mut()
+            .zip(removed.blocks.iter())
+            .for_each(|(block, removed)| *block &= !removed);
+    }
+
+    /// Puts the tiles of `added` into the cell in `row` and `column`.
+    pub(crate) fn insert(&mut self, row: usize, column: usize, added: &TileSet) {
+        self.cell_mut(row, column)
+            .iter_mut()
+            .zip(added.blocks.iter())
+            .for_each(|(block, added)| *block |= added);
+    }
+
+    /// The tiles of the cell in `row` and `column`, leaving it empty.
+    pub(crate) fn take(&mut self, row: usize, column: usize) -> TileSet {
+        let cell = self.cell_mut(row, column);
+        let set = Tiles { blocks: &*cell }.to_set();
+        cell.fill(0);
+        set
+    }
+
+    fn cell(&self, row: usize, column: usize) -> Tiles<'_> {
+        let start = (row * self.row_len + column) * self.stride;
+        Tiles {
+            blocks: &self.blocks[start..start + self.stride],
+        }
+    }
+
+    fn cell_mut(&mut self, row: usize, column: usize) -> &mut [u64] {
+        let start = (row * self.row_len + column) * self.stride;
+        &mut self.blocks[start..start + self.stride]
+    }
+}
+
+impl From<&Array2D<TileSet>> for Cells {
+    /// All sets need the same number of tiles.
+    fn from(sets: &Array2D<TileSet>) -> Cells {
+        let stride = sets
+            .elements_row_major_iter()
+            .next()
+            .map_or(0, |set| set.blocks.len());
+        Cells {
+            row_len: sets.row_len(),
+            column_len: sets.column_len(),
+            stride,
+            blocks: sets
+                .elements_row_major_iter()
+                .flat_map(|set| set.blocks.iter().copied())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tileset_test;
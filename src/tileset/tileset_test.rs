@@ -0,0 +1,67 @@
+use super::*;
+
+#[test]
+fn insert_remove_and_count() {
+    let mut set = TileSet::empty(130);
+    assert!(set.is_empty());
+    set.insert(0);
+    set.insert(64);
+    set.insert(129);
+    assert_eq!(set.len(), 3);
+    assert_eq!(set.iter().collect::<Vec<_>>(), vec![0, 64, 129]);
+    set.remove(64);
+    assert_eq!(set.iter().collect::<Vec<_>>(), vec![0, 129]);
+    assert_eq!(TileSet::full(130).len(), 130);
+}
+
+#[test]
+fn set_operations() {
+    let mut a = TileSet::full(70);
+    let b = TileSet::single(70, 66);
+    let old = a.clone();
+    a.intersect_with(&b);
+    assert_eq!(a, b);
+    assert_eq!(old.difference(&a).len(), 69);
+    assert!(a.contains(66));
+    assert!(!a.contains(1));
+
+    let mut c = TileSet::single(70, 1);
+    c.union_with(&b);
+    assert_eq!(c.iter().collect::<Vec<_>>(), vec![1, 66]);
+}
+
+#[test]
+fn cells_view_and_update_one_buffer() {
+    let mut cells = Cells::filled_with(&TileSet::full(70), 2, 3);
+    assert_eq!((cells.row_len(), cells.column_len()), (3, 2));
+    assert_eq!(cells.blocks.len(), 6 * 2);
+    assert!(cells.get(2, 0).is_none());
+
+    cells.remove(
+        1,
+        2,
+        &TileSet::full(70).difference(&TileSet::single(70, 66)),
+    );
+    assert_eq!(
+        cells.get(1, 2).unwrap().iter().collect::<Vec<_>>(),
+        vec![66]
+    );
+    assert_eq!(cells.get(1, 1).unwrap().len(), 70);
+    cells.insert(1, 2, &TileSet::single(70, 3));
+    assert_eq!(cells.take(1, 2), {
+        let mut set = TileSet::single(70, 3);
+        set.insert(66);
+        set
+    });
+    assert!(cells.get(1, 2).unwrap().is_empty());
+
+    let sets = Array2D::from_rows(&[vec![TileSet::single(70, 1), TileSet::full(70)]]).unwrap();
+    let cells = Cells::from(&sets);
+    assert_eq!(
+        cells
+            .elements_row_major_iter()
+            .map(Tiles::to_set)
+            .collect::<Vec<_>>(),
+        sets.elements_row_major_iter().cloned().collect::<Vec<_>>()
+    );
+}
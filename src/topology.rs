@@ -0,0 +1,116 @@
+use serde::Deserialize;
+
+/// How the cells of a grid are shaped and which cells border each other.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Topology {
+    /// Square cells with the sides up, right, down and left.
+    Square,
+    /// Pointy-top hexagons with every odd row shifted half a cell to the right and
+    /// the sides up-right, right, down-right, down-left, left and up-left.
+    Hex,
+    /// Cubes in a volume of stacked layers with the sides up, down, north, east,
+    /// south and west. North is up and east is right within a layer.
+    Voxel,
+}
+
+impl Topology {
+    /// The flat topology of tiles with the given number of sides.
+    pub fn from_sides(sides: usize) -> Option<Topology> {
+        match sides {
+            4 => Some(Topology::Square),
+            6 => Some(Topology::Hex),
+            _ => None,
+        }
+    }
+
+    /// The number of directions a cell has neighbors in.
+    pub fn sides(self) -> usize {
+        match self {
+            Topology::Square => 4,
+            Topology::Hex | Topology::Voxel => 6,
+        }
+    }
+
+    /// The direction pointing back from a neighbor in direction `dir`.
+    pub fn opposite(self, dir: usize) -> usize {
+        match self {
+            Topology::Voxel => [1, 0, 4, 5, 2, 3][dir],
+            _ => (dir + self.sides() / 2) % self.sides(),
+        }
+    }
+
+    /// How many distinct rotations a tile has, voxels only turn about the vertical axis.
+    pub fn rotations(self) -> usize {
+        match self {
+            Topology::Voxel => 4,
+            _ => self.sides(),
+        }
+    }
+
+    /// The clockwise rotation in degrees that moves the sides one step.
+    pub fn rotation_step(self) -> i32 {
+        360 / self.rotations() as i32
+    }
+
+    /// Turns `sides` clockwise by `steps` rotation steps.
+    pub fn rotate<T>(self, sides: &mut [T], steps: usize) {
+        match self {
+            Topology::Voxel => sides[2..].rotate_right(steps),
+            _ => sides.rotate_right(steps),
+        }
+    }
+
+    /// Mirrors `sides` left to right: the sides facing left and right trade places and,
+    /// as every side is now read the other way around, `p` labels turn into `q` labels
+    /// and back.
+    pub fn mirror(self, sides: &mut [String]) {
+        match self {
+            Topology::Square => sides.swap(1, 3),
+            Topology::Hex => sides.reverse(),
+            Topology::Voxel => sides.swap(3, 5),
+        }
+        for side in sides {
+            if let Some(rest) = side.strip_prefix("p-") {
+                *side = format!("q-{}", rest);
+            } else if let Some(rest) = side.strip_prefix("q-") {
+                *side = format!("p-{}", rest);
+            }
+        }
+    }
+
+    /// The offset in x, row and layer from a cell in row `y` to its neighbor in
+    /// direction `dir`.
+    pub fn offset(self, y: usize, dir: usize) -> (isize, isize, isize) {
+        match self {
+            Topology::Square => [(0, -1, 0), (1, 0, 0), (0, 1, 0), (-1, 0, 0)][dir],
+            Topology::Hex if y.is_multiple_of(2) => [
+                (0, -1, 0),
+                (1, 0, 0),
+                (0, 1, 0),
+                (-1, 1, 0),
+                (-1, 0, 0),
+                (-1, -1, 0),
+            ][dir],
+            Topology::Hex => [
+                (1, -1, 0),
+                (1, 0, 0),
+                (1, 1, 0),
+                (0, 1, 0),
+                (-1, 0, 0),
+                (0, -1, 0),
+            ][dir],
+            Topology::Voxel => [
+                (0, 0, 1),
+                (0, 0, -1),
+                (0, -1, 0),
+                (1, 0, 0),
+                (0, 1, 0),
+                (-1, 0, 0),
+            ][dir],
+        }
+    }
+}
+
+#[cfg(test)]
+mod topology_test;
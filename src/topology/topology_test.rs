@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use super::*;
+use crate::collapse::{solve, Backtracking, Params, Wave};
+use crate::fixtures::free_borders;
+use crate::model::Model;
+use crate::parser;
+
+#[test]
+fn opposite_steps_back() {
+    for topology in [Topology::Square, Topology::Hex, Topology::Voxel] {
+        for y in 1..3 {
+            for dir in 0..topology.sides() {
+                let (dx, dy, dz) = topology.offset(y, dir);
+                let other = (y as isize + dy) as usize;
+                let (bx, by, bz) = topology.offset(other, topology.opposite(dir));
+                assert_eq!((dx + bx, dy + by, dz + bz), (0, 0, 0));
+            }
+        }
+    }
+}
+
+#[test]
+fn hex_set_rotates_in_sixths() {
+    let set = parser::load(Path::new("res/hex.json")).unwrap();
+
+    assert_eq!(*set.topology(), Topology::Hex);
+    assert_eq!(set.fields().len(), 8);
+    let coast = set
+        .fields()
+        .iter()
+        .filter(|field| field.img_name() == "coast.png")
+        .collect::<Vec<_>>();
+    assert_eq!(
+        coast
+            .iter()
+            .map(|field| *field.rotation())
+            .collect::<Vec<_>>(),
+        vec![0, 60, 120, 180, 240, 300]
+    );
+    assert_eq!(coast[1].sides()[0], "i-Sea");
+    assert_eq!(coast[1].sides()[1], "i-Land");
+}
+
+#[test]
+fn hex_solve_is_consistent() {
+    let set = parser::load(Path::new("res/hex.json")).unwrap();
+    let model = Model::new(set.fields());
+    let borders = free_borders();
+
+    for periodic in [false, true] {
+        let params = Params::new(set.fields(), &model, &borders, periodic);
+        let mut wave = Wave::filled(&params, 6, 6).unwrap();
+        solve(&params, &mut wave, 5, &Backtracking::default()).unwrap();
+
+        let cells = wave.cells();
+        for ((y, x), tiles) in cells.enumerate_row_major() {
+            let tile = tiles.iter().next().unwrap();
+            for dir in 0..6 {
+                let (dx, dy, _) = Topology::Hex.offset(y, dir);
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                let other = if periodic {
+                    cells.get(ny.rem_euclid(6) as usize, nx.rem_euclid(6) as usize)
+                } else if nx < 0 || ny < 0 {
+                    None
+                } else {
+                    cells.get(ny as usize, nx as usize)
+                };
+                if let Some(other) = other {
+                    let other = other.iter().next().unwrap();
+                    assert!(model.compatible()[dir][tile].contains(other));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn periodic_hex_needs_even_rows() {
+    let set = parser::load(Path::new("res/hex.json")).unwrap();
+    let model = Model::new(set.fields());
+    let borders = free_borders();
+    let params = Params::new(set.fields(), &model, &borders, true);
+
+    assert!(Wave::filled(&params, 4, 3).is_err());
+    assert!(Wave::filled(&params, 4, 4).is_ok());
+}
+
+#[test]
+fn voxels_rotate_about_the_vertical_axis() {
+    let set = parser::load(Path::new("res/terrain.json")).unwrap();
+    let ramps = set
+        .fields()
+        .iter()
+        .filter(|field| field.img_name() == "ramp.vox")
+        .collect::<Vec<_>>();
+
+    assert_eq!(*set.topology(), Topology::Voxel);
+    assert_eq!(ramps.len(), 4);
+    assert_eq!(*ramps[1].rotation(), 90);
+    assert_eq!(ramps[1].sides()[..2], ["i-Air", "i-Ground"]);
+    assert_eq!(
+        ramps[1].sides()[2..],
+        ["q-Ramp", "i-Ground", "p-Ramp", "i-Surface"]
+    );
+}
+
+#[test]
+fn voxel_solve_is_consistent() {
+    let set = parser::load(Path::new("res/terrain.json")).unwrap();
+    let model = Model::with_topology(set.fields(), Topology::Voxel);
+    let borders = free_borders();
+    let params = Params::new(set.fields(), &model, &borders, false);
+    let mut wave = Wave::volume(&params, 4, 3, 3).unwrap();
+
+    solve(&params, &mut wave, 1, &Backtracking::default()).unwrap();
+    let cells = wave.cells();
+    for ((y, x), tiles) in cells.enumerate_row_major() {
+        let tile = tiles.iter().next().unwrap();
+        let (row, layer) = (y % 3, y / 3);
+        for dir in 0..6 {
+            let (dx, dy, dz) = Topology::Voxel.offset(row, dir);
+            let (nx, ny, nz) = (x as isize + dx, row as isize + dy, layer as isize + dz);
+            if (0..4).contains(&nx) && (0..3).contains(&ny) && (0..3).contains(&nz) {
+                let other = cells
+                    .get((nz * 3 + ny) as usize, nx as usize)
+                    .unwrap()
+                    .iter()
+                    .next();
+                assert!(model.compatible()[dir][tile].contains(other.unwrap()));
+            }
+        }
+    }
+}
+
+#[test]
+fn mirror_swaps_left_and_right_and_labels() {
+    let mut square = ["i-A", "p-B", "i-C", "q-D-u_x"].map(String::from);
+    Topology::Square.mirror(&mut square);
+    assert_eq!(square, ["i-A", "p-D-u_x", "i-C", "q-B"]);
+
+    let mut hex = ["0", "1", "2", "3", "4", "5"].map(String::from);
+    Topology::Hex.mirror(&mut hex);
+    assert_eq!(hex, ["5", "4", "3", "2", "1", "0"]);
+
+    let mut voxel = ["up", "down", "north", "east", "south", "west"].map(String::from);
+    Topology::Voxel.mirror(&mut voxel);
+    assert_eq!(voxel, ["up", "down", "north", "west", "south", "east"]);
+}
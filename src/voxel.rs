@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use getset::Getters;
+use itertools::Itertools;
+
+use crate::collapse::Field;
+use crate::tileset::Cells;
+
+/// A MagicaVoxel model with z pointing up: its size and the palette index of every
+/// filled voxel. Without a palette MagicaVoxel uses its default one.
+#[derive(Clone, PartialEq, Eq, Debug, new, Getters)]
+#[getset(get = "pub")]
+pub struct Voxels {
+    /// The extent along x, y and z.
+    size: [usize; 3],
+    /// The position and palette index of every filled voxel.
+    voxels: Vec<([usize; 3], u8)>,
+    /// The RGBA colors of the palette, if the model has its own.
+    palette: Option<Vec<[u8; 4]>>,
+}
+
+impl Voxels {
+    /// Reads the first model of the `.vox` file at `path`.
+    pub fn load(path: &Path) -> Result<Voxels, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Voxels::parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Writes the model as a `.vox` file to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_bytes()?).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Reads the first model of a `.vox` file.
+    pub fn parse(bytes: &[u8]) -> Result<Voxels, String> {
+        let int = |at: usize| -> Result<usize, String> {
+            bytes
+                .get(at..at + 4)
+                .map(|int| u32::from_le_bytes(int.try_into().unwrap()) as usize)
+                .ok_or_else(|| "unexpected end of file".to_string())
+        };
+        if bytes.get(0..4) != Some(b"VOX ") || bytes.get(8..12) != Some(b"MAIN") {
+            return Err("not a vox file".to_string());
+        }
+        let end = 20 + int(12)? + int(16)?;
+        let mut at = 20 + int(12)?;
+        let (mut size, mut voxels, mut palette) = (None, None, None);
+        while at + 12 <= end.min(bytes.len()) {
+            let content = at + 12;
+            match &bytes[at..at + 4] {
+                b"SIZE" if size.is_none() => {
+                    size = Some([int(content)?, int(content + 4)?, int(content + 8)?]);
+                }
+                b"XYZI" if voxels.is_none() => {
+                    let count = int(content)?;
+                    let data = bytes
+                        .get(content + 4..content + 4 + count * 4)
+                        .ok_or("unexpected end of file")?;
+                    voxels = Some(
+                        data.chunks(4)
+                            .map(|v| ([v[0] as usize, v[1] as usize, v[2] as usize], v[3]))
+                            .collect_vec(),
+                    );
+                }
+                b"RGBA" => {
+                    let data = bytes
+                        .get(content..content + 1024)
+                        .ok_or("unexpected end of file")?;
+                    palette = Some(data.chunks(4).map(|c| [c[0], c[1], c[2], c[3]]).collect());
+                }
+                _ => {}
+            }
+            at = content + int(at + 4)? + int(at + 8)?;
+        }
+        match (size, voxels) {
+            (Some(size), Some(voxels)) => Ok(Voxels::new(size, voxels, palette)),
+            _ => Err("vox file has no model".to_string()),
+        }
+    }
+
+    /// Fails if the model is larger than the 256 voxels a `.vox` model may span.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        if self.size.iter().any(|&len| len > 256) {
+            return Err(format!(
+                "a vox model is at most 256 voxels long, not {:?}",
+                self.size
+            ));
+        }
+        let chunk = |id: &[u8], content: Vec<u8>| {
+            [id, &(content.len() as u32).to_le_bytes(), &[0; 4], &content].concat()
+        };
+        let mut children = chunk(
+            b"SIZE",
+            self.size
+                .iter()
+                .flat_map(|&len| (len as u32).to_le_bytes())
+                .collect(),
+        );
+        let mut xyzi = (self.voxels.len() as u32).to_le_bytes().to_vec();
+        for ([x, y, z], color) in &self.voxels {
+            xyzi.extend([*x as u8, *y as u8, *z as u8, *color]);
+        }
+        children.extend(chunk(b"XYZI", xyzi));
+        if let Some(palette) = &self.palette {
+            children.extend(chunk(b"RGBA", palette.concat()));
+        }
+        Ok([
+            b"VOX ".as_slice(),
+            &150u32.to_le_bytes(),
+            b"MAIN",
+            &[0; 4],
+            &(children.len() as u32).to_le_bytes(),
+            &children,
+        ]
+        .concat())
+    }
+
+    /// Mirrored east to west, i.e. along x.
+    pub fn mirrored(&self) -> Voxels {
+        let voxels = self
+            .voxels
+            .iter()
+            .map(|&([x, y, z], color)| ([self.size[0] - 1 - x, y, z], color))
+            .collect();
+        Voxels::new(self.size, voxels, self.palette.clone())
+    }
+
+    /// Turned clockwise about the vertical axis by `quarter_turns`, seen from above
+    /// with y pointing north. Only models with a square footprint can be turned.
+    pub fn rotated(&self, quarter_turns: usize) -> Voxels {
+        let [x_size, y_size, z_size] = self.size;
+        debug_assert_eq!(x_size, y_size);
+        let voxels = self
+            .voxels
+            .iter()
+            .map(|&([x, y, z], color)| {
+                let (x, y) = (0..quarter_turns % 4).fold((x, y), |(x, y), _| (y, x_size - 1 - x));
+                ([x, y, z], color)
+            })
+            .collect();
+        Voxels::new([x_size, y_size, z_size], voxels, self.palette.clone())
+    }
+}
+
+/// Builds one model from a collapsed volume of `layers` stacked in the rows of `cells`,
+/// placing the model of each cell's field, mirrored and rotated like the field. The
+/// first row of a layer lies north and the first layer at the bottom. Undecided cells
+/// stay empty.
+pub fn assemble(
+    fields: &[Field],
+    models: &HashMap<String, Voxels>,
+    cells: &Cells,
+    layers: usize,
+) -> Result<Voxels, String> {
+    let sizes = models
+        .values()
+        .map(|model| model.size)
+        .unique()
+        .collect_vec();
+    let [x_step, y_step, z_step] = match sizes[..] {
+        [size] => size,
+        _ => return Err("the tile models need one common size".to_string()),
+    };
+    let rows = cells.column_len() / layers;
+    let mut voxels = Vec::new();
+    for ((y, x), tiles) in cells.enumerate_row_major() {
+        if tiles.len() != 1 {
+            continue;
+        }
+        let field = &fields[tiles.iter().next().unwrap()];
+        let model = models
+            .get(field.img_name())
+            .ok_or(format!("no model for {}", field.img_name()))?;
+        let model = match field.mirrored() {
+            true => model.mirrored(),
+            false => model.clone(),
+        }
+        .rotated(field.rotation().rem_euclid(360) as usize / 90);
+        let (row, layer) = (y % rows, y / rows);
+        let offset = [x * x_step, (rows - 1 - row) * y_step, layer * z_step];
+        voxels.extend(model.voxels.iter().map(|&([vx, vy, vz], color)| {
+            ([offset[0] + vx, offset[1] + vy, offset[2] + vz], color)
+        }));
+    }
+    let palette = fields
+        .iter()
+        .filter_map(|field| models.get(field.img_name()))
+        .find_map(|model| model.palette.clone());
+    Ok(Voxels::new(
+        [cells.row_len() * x_step, rows * y_step, layers * z_step],
+        voxels,
+        palette,
+    ))
+}
+
+#[cfg(test)]
+mod voxel_test;
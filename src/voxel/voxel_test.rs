@@ -0,0 +1,82 @@
+use array2d::Array2D;
+
+use super::*;
+use crate::tileset::TileSet;
+
+fn field(name: &str, rotation: i32) -> Field {
+    Field::new(name.to_string(), rotation, vec!["i-A".to_string(); 6], 1)
+}
+
+#[test]
+fn bytes_round_trip() {
+    let voxels = Voxels::new(
+        [3, 2, 1],
+        vec![([0, 0, 0], 1), ([2, 1, 0], 7)],
+        Some(vec![[1, 2, 3, 255]; 256]),
+    );
+
+    assert_eq!(Voxels::parse(&voxels.to_bytes().unwrap()), Ok(voxels));
+    assert!(Voxels::parse(b"PNG").is_err());
+    assert!(Voxels::new([300, 1, 1], Vec::new(), None)
+        .to_bytes()
+        .is_err());
+}
+
+#[test]
+fn loads_tile_models() {
+    let ramp = Voxels::load(Path::new("res/vox/terrain/ramp.vox")).unwrap();
+
+    assert_eq!(*ramp.size(), [4, 4, 4]);
+    assert_eq!(ramp.voxels().len(), 40);
+    assert!(ramp.palette().is_some());
+}
+
+#[test]
+fn rotates_clockwise_from_above() {
+    let north = Voxels::new([4, 4, 4], vec![([1, 3, 2], 1)], None);
+
+    assert_eq!(north.rotated(1).voxels(), &vec![([3, 2, 2], 1)]);
+    assert_eq!(north.rotated(2).voxels(), &vec![([2, 0, 2], 1)]);
+    assert_eq!(north.rotated(4), north);
+}
+
+#[test]
+fn assembles_layers_bottom_up() {
+    let fields = vec![field("ground", 0), field("corner", 90)];
+    let models = HashMap::from([
+        (
+            "ground".to_string(),
+            Voxels::new([2, 2, 2], vec![([0, 0, 0], 1)], None),
+        ),
+        (
+            "corner".to_string(),
+            Voxels::new([2, 2, 2], vec![([0, 1, 1], 2)], None),
+        ),
+    ]);
+    // one layer of two rows below a layer with the corner in its first row
+    let cells = Array2D::from_rows(&[
+        vec![TileSet::single(2, 0)],
+        vec![TileSet::full(2)],
+        vec![TileSet::single(2, 1)],
+        vec![TileSet::single(2, 0)],
+    ])
+    .unwrap();
+    let volume = assemble(&fields, &models, &Cells::from(&cells), 2).unwrap();
+
+    assert_eq!(*volume.size(), [2, 4, 4]);
+    assert_eq!(
+        volume.voxels(),
+        &vec![([0, 2, 0], 1), ([1, 3, 3], 2), ([0, 0, 2], 1)]
+    );
+}
+
+#[test]
+fn mirroring_flips_x() {
+    let model = Voxels::new([3, 2, 1], vec![([0, 1, 0], 1), ([2, 0, 0], 2)], None);
+
+    assert_eq!(
+        *model.mirrored().voxels(),
+        vec![([2, 1, 0], 1), ([0, 0, 0], 2)]
+    );
+    assert_eq!(model.mirrored().mirrored(), model);
+}
@@ -0,0 +1,55 @@
+use array2d::Array2D;
+
+use crate::collapse::Coord;
+
+/// Multipliers for the weights of tiles depending on where a cell lies, so the same set
+/// can favor some tiles in one region and others elsewhere. Every tile without a map
+/// keeps its weight everywhere.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Weights {
+    maps: Vec<Option<Array2D<f64>>>,
+}
+
+impl Weights {
+    /// No maps for any of `tiles` tiles.
+    pub fn new(tiles: usize) -> Weights {
+        Weights {
+            maps: vec![None; tiles],
+        }
+    }
+
+    /// Multiplies the weight of `tile` by `map`, which is stretched over the grid, so a
+    /// map of one value per cell biases single cells and a coarse one whole regions.
+    pub fn set(&mut self, tile: usize, map: Array2D<f64>) {
+        self.maps[tile] = Some(map);
+    }
+
+    /// The multiplier of `tile` at `pos` of a `width` by `height` grid.
+    pub fn multiplier(&self, tile: usize, pos: Coord, width: usize, height: usize) -> f64 {
+        match &self.maps[tile] {
+            Some(map) => {
+                let x = pos.x() * map.row_len() / width;
+                let y = pos.y() * map.column_len() / height;
+                map[(y, x)]
+            }
+            None => 1.0,
+        }
+    }
+}
+
+/// The brightness of every pixel of a grayscale mask scaled to `0..=scale`, black
+/// never choosing the tile and white multiplying its weight by `scale`.
+pub fn mask(image: &Array2D<u32>, scale: f64) -> Array2D<f64> {
+    let values = image
+        .elements_row_major_iter()
+        .map(|color| {
+            let [r, g, b, _] = color.to_be_bytes();
+            (r as f64 + g as f64 + b as f64) / (3.0 * 255.0) * scale
+        })
+        .collect::<Vec<_>>();
+    Array2D::from_row_major(&values, image.column_len(), image.row_len())
+        .expect("mask has the size of the image")
+}
+
+#[cfg(test)]
+mod weights_test;
@@ -0,0 +1,27 @@
+use super::*;
+
+#[test]
+fn maps_stretch_over_the_grid() {
+    let mut weights = Weights::new(2);
+    weights.set(
+        1,
+        Array2D::from_rows(&[vec![1.0, 2.0], vec![3.0, 4.0]]).unwrap(),
+    );
+
+    assert_eq!(weights.multiplier(0, Coord::new(5, 5), 6, 6), 1.0);
+    assert_eq!(weights.multiplier(1, Coord::new(0, 0), 6, 6), 1.0);
+    assert_eq!(weights.multiplier(1, Coord::new(2, 0), 6, 6), 1.0);
+    assert_eq!(weights.multiplier(1, Coord::new(3, 0), 6, 6), 2.0);
+    assert_eq!(weights.multiplier(1, Coord::new(1, 4), 6, 6), 3.0);
+    assert_eq!(weights.multiplier(1, Coord::new(5, 5), 6, 6), 4.0);
+}
+
+#[test]
+fn masks_scale_brightness() {
+    let image = Array2D::from_rows(&[vec![0x0000_00ff, 0xffff_ffff, 0x8080_80ff]]).unwrap();
+    let mask = mask(&image, 4.0);
+
+    assert_eq!(mask[(0, 0)], 0.0);
+    assert_eq!(mask[(0, 1)], 4.0);
+    assert!((mask[(0, 2)] - 2.0).abs() < 0.02);
+}
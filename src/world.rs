@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use array2d::Array2D;
+use getset::Getters;
+
+use crate::boundary::{Border, Boundary};
+use crate::collapse::{solve, Backtracking, Contradiction, Coord, Field, Params, Wave};
+use crate::model::Model;
+use crate::topology::Topology;
+
+/// How many seeds a chunk tries before it starts dropping seam positions.
+const RETRIES: u64 = 4;
+
+/// A collapsed chunk and the seam positions, as side (up, right, down, left) and
+/// index along it, that had to be dropped because no tiles fit all of its neighbors.
+#[derive(Clone, PartialEq, Eq, Debug, new, Getters)]
+#[getset(get = "pub")]
+pub struct Chunk {
+    /// The tile of every field.
+    tiles: Array2D<usize>,
+    /// The dropped seam positions.
+    broken: Vec<(usize, usize)>,
+}
+
+/// An unbounded square map generated in chunks of `size` by `size` fields on demand.
+/// Every chunk is seeded from its coordinate and bordered by the tiles of the neighbor
+/// chunks generated before it, so chunks only depend on the order they are asked for.
+#[derive(Getters)]
+#[getset(get = "pub")]
+pub struct World<'w> {
+    /// Every field of the set, indexed by tile.
+    fields: &'w Vec<Field>,
+    /// Which tiles fit next to each other.
+    model: &'w Model,
+    /// The width and height of a chunk.
+    size: usize,
+    /// The seed every chunk seed is mixed from.
+    seed: u64,
+    /// The limits for backtracking within a chunk.
+    backtracking: Backtracking,
+    /// The chunks generated so far, by chunk coordinate.
+    chunks: HashMap<(i64, i64), Chunk>,
+}
+
+impl<'w> World<'w> {
+    /// An empty world of `size` by `size` chunks. Fails unless the model is square and
+    /// chunks hold at least one field.
+    pub fn new(
+        fields: &'w Vec<Field>,
+        model: &'w Model,
+        size: usize,
+        seed: u64,
+        backtracking: Backtracking,
+    ) -> Result<World<'w>, String> {
+        if *model.topology() != Topology::Square {
+            return Err("only square worlds can be generated in chunks".to_string());
+        }
+        if size == 0 {
+            return Err("chunks need at least one field".to_string());
+        }
+        Ok(World {
+            fields,
+            model,
+            size,
+            seed,
+            backtracking,
+            chunks: HashMap::new(),
+        })
+    }
+
+    /// The seed of the chunk at `x`, `y`, mixed from the world seed.
+    pub fn chunk_seed(&self, x: i64, y: i64) -> u64 {
+        let mut seed = self.seed
+            ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+        seed = (seed ^ (seed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        seed = (seed ^ (seed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        seed ^ (seed >> 31)
+    }
+
+    /// The chunk at `x`, `y`, generated first if it does not exist yet. When its seams
+    /// cannot be satisfied it retries with other seeds and then frees one seam position
+    /// after the other, nearest to the contradiction first. Fails only if it cannot be
+    /// solved without any seams.
+    pub fn chunk(&mut self, x: i64, y: i64) -> Result<&Chunk, Contradiction> {
+        if !self.chunks.contains_key(&(x, y)) {
+            let chunk = self.generate(x, y)?;
+            self.chunks.insert((x, y), chunk);
+        }
+        Ok(&self.chunks[&(x, y)])
+    }
+
+    /// The tiles of the `width` by `height` fields starting at field `x`, `y`,
+    /// generating the chunks they lie in row by row.
+    pub fn region(
+        &mut self,
+        x: i64,
+        y: i64,
+        width: usize,
+        height: usize,
+    ) -> Result<Array2D<usize>, Contradiction> {
+        let size = self.size as i64;
+        let mut tiles = Vec::with_capacity(width * height);
+        for field_y in y..y + height as i64 {
+            for field_x in x..x + width as i64 {
+                let chunk = self.chunk(field_x.div_euclid(size), field_y.div_euclid(size))?;
+                tiles.push(
+                    chunk.tiles[(
+                        field_y.rem_euclid(size) as usize,
+                        field_x.rem_euclid(size) as usize,
+                    )],
+                );
+            }
+        }
+        Ok(Array2D::from_row_major(&tiles, height, width).expect("region has its size"))
+    }
+
+    fn generate(&self, x: i64, y: i64) -> Result<Chunk, Contradiction> {
+        let mut borders = self.seams(x, y);
+        let mut broken = Vec::new();
+        loop {
+            let params = Params::new(self.fields, self.model, &borders, false);
+            let mut last = None;
+            for attempt in 0..RETRIES {
+                let mut wave = Wave::filled(&params, self.size, self.size)
+                    .expect("seams are as long as the chunk");
+                match solve(
+                    &params,
+                    &mut wave,
+                    self.chunk_seed(x, y).wrapping_add(attempt),
+                    &self.backtracking,
+                ) {
+                    Ok(()) => {
+                        let tiles: Vec<usize> = wave
+                            .cells()
+                            .elements_row_major_iter()
+                            .map(|tiles| tiles.iter().next().unwrap())
+                            .collect();
+                        return Ok(Chunk::new(
+                            Array2D::from_row_major(&tiles, self.size, self.size).unwrap(),
+                            broken,
+                        ));
+                    }
+                    Err(contradiction) => last = Some(contradiction),
+                }
+            }
+            let last = last.unwrap();
+            match self.nearest_seam(&borders, *last.pos()) {
+                Some((dir, i)) => {
+                    if let Border::PerPosition(boundaries) = &mut borders[dir] {
+                        boundaries[i] = Boundary::Free;
+                    }
+                    broken.push((dir, i));
+                }
+                None => return Err(last),
+            }
+        }
+    }
+
+    /// The side and index of the seam position still in `borders` whose edge field
+    /// is closest to `pos`.
+    fn nearest_seam(&self, borders: &[Border; 4], pos: Coord) -> Option<(usize, usize)> {
+        let last = self.size - 1;
+        let edge = |dir: usize, i: usize| match dir {
+            0 => Coord::new(i, 0),
+            1 => Coord::new(last, i),
+            2 => Coord::new(i, last),
+            _ => Coord::new(0, i),
+        };
+        borders
+            .iter()
+            .enumerate()
+            .flat_map(|(dir, border)| match border {
+                Border::PerPosition(boundaries) => boundaries
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, boundary)| **boundary != Boundary::Free)
+                    .map(|(i, _)| (dir, i))
+                    .collect(),
+                Border::All(_) => Vec::new(),
+            })
+            .min_by_key(|&(dir, i)| {
+                let field = edge(dir, i);
+                field
+                    .x()
+                    .abs_diff(*pos.x())
+                    .max(field.y().abs_diff(*pos.y()))
+            })
+    }
+
+    /// The edge tiles of the generated neighbor chunks as borders, free where there is none.
+    fn seams(&self, x: i64, y: i64) -> [Border; 4] {
+        let last = self.size - 1;
+        let seam = |chunk: Option<&Chunk>, tile: &dyn Fn(&Array2D<usize>, usize) -> usize| {
+            chunk.map_or(Border::free(), |chunk| {
+                Border::PerPosition(
+                    (0..self.size)
+                        .map(|i| Boundary::Tile(tile(&chunk.tiles, i)))
+                        .collect(),
+                )
+            })
+        };
+        [
+            seam(self.chunks.get(&(x, y - 1)), &|tiles, i| tiles[(last, i)]),
+            seam(self.chunks.get(&(x + 1, y)), &|tiles, i| tiles[(i, 0)]),
+            seam(self.chunks.get(&(x, y + 1)), &|tiles, i| tiles[(0, i)]),
+            seam(self.chunks.get(&(x - 1, y)), &|tiles, i| tiles[(i, last)]),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod world_test;
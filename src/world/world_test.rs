@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use array2d::Array2D;
+
+use super::*;
+use crate::fixtures::field;
+use crate::parser;
+
+#[test]
+fn seams_fit_across_chunks() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+    let mut world = World::new(set.fields(), &model, 6, 7, Backtracking::default()).unwrap();
+    let region = world.region(-6, -6, 18, 18).unwrap();
+
+    assert!(world
+        .chunks()
+        .values()
+        .all(|chunk| chunk.broken().is_empty()));
+    for ((y, x), &tile) in region.enumerate_row_major() {
+        if let Some(&right) = region.get(y, x + 1) {
+            assert!(model.compatible()[1][tile].contains(right));
+        }
+        if let Some(&below) = region.get(y + 1, x) {
+            assert!(model.compatible()[2][tile].contains(below));
+        }
+    }
+}
+
+#[test]
+fn chunks_are_seeded_by_coordinate() {
+    let set = parser::load(Path::new("res/circuit.json")).unwrap();
+    let model = Model::new(set.fields());
+    let mut a = World::new(set.fields(), &model, 5, 3, Backtracking::default()).unwrap();
+    let mut b = World::new(set.fields(), &model, 5, 3, Backtracking::default()).unwrap();
+
+    assert_ne!(a.chunk_seed(0, 1), a.chunk_seed(1, 0));
+    assert_eq!(a.chunk(4, -2).unwrap(), b.chunk(4, -2).unwrap());
+    assert_eq!(
+        a.region(0, 0, 10, 5).unwrap(),
+        b.region(0, 0, 10, 5).unwrap()
+    );
+}
+
+#[test]
+fn unsatisfiable_seam_is_dropped() {
+    // a and b never touch, so every chunk is all a or all b
+    let fields = vec![field("a", ["i-A"; 4]), field("b", ["i-B"; 4])];
+    let model = Model::new(&fields);
+    let seed = (0..64)
+        .find(|&seed| {
+            let mut world = World::new(&fields, &model, 3, seed, Backtracking::default()).unwrap();
+            world.chunk(0, 0).unwrap().tiles()[(0, 0)] != world.chunk(2, 0).unwrap().tiles()[(0, 0)]
+        })
+        .unwrap();
+    let mut world = World::new(&fields, &model, 3, seed, Backtracking::default()).unwrap();
+    world.chunk(0, 0).unwrap();
+    world.chunk(2, 0).unwrap();
+    let between = world.chunk(1, 0).unwrap();
+
+    let right = (0..3).map(|i| (1, i)).collect::<Vec<_>>();
+    let left = (0..3).map(|i| (3, i)).collect::<Vec<_>>();
+    assert!(
+        right.iter().all(|seam| between.broken().contains(seam))
+            || left.iter().all(|seam| between.broken().contains(seam))
+    );
+    assert!(between
+        .tiles()
+        .elements_row_major_iter()
+        .all(|&tile| tile == between.tiles()[(0, 0)]));
+}
+
+#[test]
+fn only_conflicting_seam_positions_are_dropped() {
+    let fields = vec![field("a", ["i-A"; 4]), field("b", ["i-B"; 4])];
+    let model = Model::new(&fields);
+    let mut world = World::new(&fields, &model, 3, 0, Backtracking::default()).unwrap();
+    let left = Array2D::from_rows(&[vec![0, 0, 0], vec![0, 0, 1], vec![0, 0, 0]]).unwrap();
+    world.chunks.insert((0, 0), Chunk::new(left, Vec::new()));
+    let chunk = world.chunk(1, 0).unwrap();
+
+    assert!(!chunk.broken().is_empty() && chunk.broken().len() < 3);
+    assert!(chunk.broken().iter().all(|&(dir, _)| dir == 3));
+    assert!(chunk
+        .tiles()
+        .elements_row_major_iter()
+        .all(|&tile| tile == 0));
+}
+
+#[test]
+fn only_square_worlds() {
+    let set = parser::load(Path::new("res/hex.json")).unwrap();
+    let model = Model::new(set.fields());
+
+    assert!(World::new(set.fields(), &model, 4, 0, Backtracking::default()).is_err());
+}